@@ -0,0 +1,30 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rusty_micrograd::{data::to_values, datasets::make_moons, mlp::MLP, random};
+
+// Batch inference through the autodiff graph (`MLP::call`) against plain
+// floats (`MLP::predict`).
+fn batch_inference(c: &mut Criterion) {
+    random::seed(0);
+    let model = MLP::new(2, vec![16, 16, 1]);
+    let xs = make_moons(256, 0.1, 0).xs;
+
+    let mut group = c.benchmark_group("batch_inference_256");
+    group.bench_function("call", |b| {
+        b.iter(|| {
+            xs.iter()
+                .map(|x| model.call(&to_values(black_box(x)))[0].borrow().data)
+                .sum::<f64>()
+        })
+    });
+    group.bench_function("predict", |b| {
+        b.iter(|| {
+            xs.iter()
+                .map(|x| model.predict(black_box(x))[0])
+                .sum::<f64>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, batch_inference);
+criterion_main!(benches);
@@ -0,0 +1,188 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::*;
+
+/// The nonlinearity applied to the outputs of a neuron or layer.
+///
+/// `Softmax` normalizes across all the neurons of a layer, so a lone
+/// `Neuron` leaves its output linear and the `Layer` applies it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activation {
+    Tanh,
+    ReLU,
+    Sigmoid,
+    Softmax,
+    Linear,
+}
+
+/// `true` is the tanh of the original micrograd `nonlin` flag.
+impl From<bool> for Activation {
+    fn from(nonlin: bool) -> Self {
+        if nonlin {
+            Activation::Tanh
+        } else {
+            Activation::Linear
+        }
+    }
+}
+
+impl fmt::Display for Activation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Activation::Tanh => write!(f, "tanh"),
+            Activation::ReLU => write!(f, "relu"),
+            Activation::Sigmoid => write!(f, "sigmoid"),
+            Activation::Softmax => write!(f, "softmax"),
+            Activation::Linear => write!(f, "linear"),
+        }
+    }
+}
+
+impl Activation {
+    /// Applies an elementwise activation. `Softmax` and `Linear` pass `x`
+    /// through.
+    pub fn call(&self, x: Value) -> Value {
+        match self {
+            Activation::Tanh => x._tanh("output"),
+            Activation::ReLU => x._relu("output"),
+            Activation::Sigmoid => x._sigmoid("output"),
+            Activation::Softmax | Activation::Linear => x,
+        }
+    }
+
+    /// `call` on a plain float.
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::ReLU => x.max(0.0),
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::Softmax | Activation::Linear => x,
+        }
+    }
+
+    /// Applies the activation across a layer: `Softmax` over all of
+    /// `outputs`, which must come from `call`; the others did their work
+    /// in `call` already.
+    pub fn call_layer(&self, outputs: Vec<Value>) -> Vec<Value> {
+        match self {
+            Activation::Softmax => softmax(&outputs),
+            _ => outputs,
+        }
+    }
+
+    /// `call_layer` on plain floats.
+    pub fn apply_layer(&self, outputs: Vec<f64>) -> Vec<f64> {
+        match self {
+            Activation::Softmax => {
+                let max = outputs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let lse = max + outputs.iter().map(|x| (x - max).exp()).sum::<f64>().ln();
+                outputs.iter().map(|x| (x - lse).exp()).collect()
+            }
+            _ => outputs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+    use crate::mlp::MLP;
+
+    /// The forward pass of `model` worked out by hand from its weights, with
+    /// `activations[i]` applied to every output of layer `i`.
+    fn by_hand(model: &MLP, x: &[f64], activations: &[fn(f64) -> f64]) -> Vec<f64> {
+        model
+            .0
+            .iter()
+            .zip(activations)
+            .fold(x.to_vec(), |x, (layer, activation)| {
+                layer
+                    .weights()
+                    .iter()
+                    .zip(layer.biases())
+                    .map(|(w, b)| activation(w.iter().zip(&x).map(|(w, x)| w * x).sum::<f64>() + b))
+                    .collect()
+            })
+    }
+
+    fn call(model: &MLP, x: &[f64]) -> Vec<f64> {
+        let x = x
+            .iter()
+            .map(|x| Value::new(*x, "x"))
+            .collect::<Vec<Value>>();
+        model.call(&x).iter().map(|v| v.borrow().data).collect()
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn the_last_layer_is_linear_by_default() {
+        let mut rng = ChaCha12Rng::seed_from_u64(12);
+        let model = MLP::with_rng(2, vec![3, 3, 2], &mut rng);
+        let layers = model.0.iter().map(|l| l.activation()).collect::<Vec<_>>();
+        assert_eq!(
+            layers,
+            [Activation::Tanh, Activation::Tanh, Activation::Linear]
+        );
+
+        let x = [0.7, -1.3];
+        let expected = by_hand(&model, &x, &[f64::tanh, f64::tanh, |z| z]);
+        assert_close(&call(&model, &x), &expected);
+
+        let model = model.output_activation(Activation::Tanh);
+        let squashed = expected.iter().map(|z| z.tanh()).collect::<Vec<f64>>();
+        assert_close(&call(&model, &x), &squashed);
+    }
+
+    #[test]
+    fn each_layer_gets_its_own_activation() {
+        let mut rng = ChaCha12Rng::seed_from_u64(12);
+        let model = MLP::with_rng(2, vec![4, 3, 2], &mut rng).activations(vec![
+            Activation::ReLU,
+            Activation::Sigmoid,
+            Activation::Tanh,
+        ]);
+
+        let relu = |z: f64| z.max(0.0);
+        let sigmoid = |z: f64| 1.0 / (1.0 + (-z).exp());
+        for x in [[0.7, -1.3], [-2.0, 0.1], [0.0, 0.0]] {
+            let expected = by_hand(&model, &x, &[relu, sigmoid, f64::tanh]);
+            assert_close(&call(&model, &x), &expected);
+            assert_close(&model.predict(&x), &expected);
+        }
+    }
+
+    #[test]
+    fn a_softmax_head_gives_a_distribution() {
+        let mut rng = ChaCha12Rng::seed_from_u64(12);
+        let model = MLP::with_rng(2, vec![4, 3], &mut rng).output_activation(Activation::Softmax);
+        assert_eq!(model.0[0].activation(), Activation::Tanh);
+
+        for x in [[0.7, -1.3], [-2.0, 0.1], [300.0, -500.0]] {
+            for probs in [call(&model, &x), model.predict(&x)] {
+                assert_eq!(probs.len(), 3);
+                assert!(probs.iter().all(|p| (0.0..=1.0).contains(p)), "{:?}", probs);
+                assert!(
+                    (probs.iter().sum::<f64>() - 1.0).abs() < 1e-12,
+                    "{:?}",
+                    probs
+                );
+            }
+        }
+
+        // large logits don't overflow
+        let probs = Activation::Softmax.apply_layer(vec![1000.0, 0.0, -1000.0]);
+        assert_eq!(probs, [1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{engine::*, module::Module, optim::OptimizerState, random::RngState};
+
+const MAGIC: &[u8; 8] = b"RMGRADCK";
+const VERSION: u32 = 1;
+
+/// A snapshot of a training run, enough to resume it exactly.
+///
+/// The binary layout is the magic bytes, a little-endian `u32` version, the
+/// fields below and a CRC-32 of everything before it. Parameters are stored
+/// in `parameters()` order, the same order the optimizer state is indexed by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Epochs completed.
+    pub epoch: u64,
+    /// Optimizer steps taken.
+    pub step: u64,
+    pub params: Vec<f64>,
+    /// The model's `Module::buffers`, e.g. batch norm running statistics.
+    pub buffers: Vec<f64>,
+    pub optimizer: OptimizerState,
+    /// The crate-wide RNG, see `random::state`.
+    pub rng: RngState,
+    /// The shuffling RNG of the data loader, if it shuffles.
+    pub loader_rng: Option<RngState>,
+    /// The position of the learning-rate scheduler, if there is one.
+    pub scheduler_epoch: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Writes the current data of `params` into a fresh `params` list.
+    pub fn params_of(params: &[Value]) -> Vec<f64> {
+        params.iter().map(|p| p.borrow().data).collect()
+    }
+
+    /// Copies the stored parameters into `params`, which must have the same
+    /// length, i.e. come from a model of the same shape.
+    pub fn restore_params(&self, params: &[Value]) -> io::Result<()> {
+        if params.len() != self.params.len() {
+            return Err(invalid(format!(
+                "the checkpoint holds {} parameters, the model has {}",
+                self.params.len(),
+                params.len()
+            )));
+        }
+
+        for (p, data) in params.iter().zip(&self.params) {
+            let mut p = p.0.borrow_mut();
+            p.data = *data;
+            p.grad = 0.0;
+        }
+        Ok(())
+    }
+
+    /// Loads the stored buffers into `model`, which must have as many.
+    pub fn restore_buffers(&self, model: &dyn Module) -> io::Result<()> {
+        let expected = model.buffers().len();
+        if expected != self.buffers.len() {
+            return Err(invalid(format!(
+                "the checkpoint holds {} buffer values, the model has {}",
+                self.buffers.len(),
+                expected
+            )));
+        }
+
+        model.load_buffers(&self.buffers);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::new());
+        out.0.extend_from_slice(MAGIC);
+        out.u32(VERSION);
+
+        out.u64(self.epoch);
+        out.u64(self.step);
+        out.f64s(&self.params);
+        out.f64s(&self.buffers);
+
+        match &self.optimizer {
+            OptimizerState::Stateless => out.u8(0),
+            OptimizerState::Sgd {
+                lr,
+                momentum,
+                velocity,
+            } => {
+                out.u8(1);
+                out.f64(*lr);
+                out.f64(*momentum);
+                out.f64s(velocity);
+            }
+            OptimizerState::Adam {
+                lr,
+                beta1,
+                beta2,
+                eps,
+                m,
+                v,
+                t,
+            } => {
+                out.u8(2);
+                for x in [lr, beta1, beta2, eps] {
+                    out.f64(*x);
+                }
+                out.f64s(m);
+                out.f64s(v);
+                out.u64(*t);
+            }
+        }
+
+        out.rng(&self.rng);
+        match &self.loader_rng {
+            Some(state) => {
+                out.u8(1);
+                out.rng(state);
+            }
+            None => out.u8(0),
+        }
+        match self.scheduler_epoch {
+            Some(epoch) => {
+                out.u8(1);
+                out.u64(epoch);
+            }
+            None => out.u8(0),
+        }
+
+        let checksum = crc32fast::hash(&out.0);
+        out.u32(checksum);
+        out.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < MAGIC.len() + 8 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a checkpoint file".to_string()));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - 4);
+        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+        if crc32fast::hash(body) != checksum {
+            return Err(invalid("checkpoint checksum mismatch".to_string()));
+        }
+
+        let mut input = Reader(&body[MAGIC.len()..]);
+        let version = input.u32()?;
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unsupported checkpoint version {}",
+                version
+            )));
+        }
+
+        let epoch = input.u64()?;
+        let step = input.u64()?;
+        let params = input.f64s()?;
+        let buffers = input.f64s()?;
+
+        let optimizer = match input.u8()? {
+            0 => OptimizerState::Stateless,
+            1 => OptimizerState::Sgd {
+                lr: input.f64()?,
+                momentum: input.f64()?,
+                velocity: input.f64s()?,
+            },
+            2 => OptimizerState::Adam {
+                lr: input.f64()?,
+                beta1: input.f64()?,
+                beta2: input.f64()?,
+                eps: input.f64()?,
+                m: input.f64s()?,
+                v: input.f64s()?,
+                t: input.u64()?,
+            },
+            tag => return Err(invalid(format!("unknown optimizer tag {}", tag))),
+        };
+
+        let rng = input.rng()?;
+        let loader_rng = match input.u8()? {
+            0 => None,
+            _ => Some(input.rng()?),
+        };
+        let scheduler_epoch = match input.u8()? {
+            0 => None,
+            _ => Some(input.u64()?),
+        };
+
+        if !input.0.is_empty() {
+            return Err(invalid("trailing bytes in checkpoint".to_string()));
+        }
+
+        Ok(Checkpoint {
+            epoch,
+            step,
+            params,
+            buffers,
+            optimizer,
+            rng,
+            loader_rng,
+            scheduler_epoch,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Checkpoint::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn f64(&mut self, x: f64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn f64s(&mut self, xs: &[f64]) {
+        self.u64(xs.len() as u64);
+        xs.iter().for_each(|x| self.f64(*x));
+    }
+
+    fn rng(&mut self, state: &RngState) {
+        self.0.extend_from_slice(&state.seed);
+        self.u64(state.stream);
+        self.0.extend_from_slice(&state.word_pos.to_le_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(invalid("truncated checkpoint".to_string()));
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn f64s(&mut self) -> io::Result<Vec<f64>> {
+        let len = self.u64()? as usize;
+        if self.0.len() / 8 < len {
+            return Err(invalid("truncated checkpoint".to_string()));
+        }
+        (0..len).map(|_| self.f64()).collect()
+    }
+
+    fn rng(&mut self) -> io::Result<RngState> {
+        Ok(RngState {
+            seed: self.take()?,
+            stream: self.u64()?,
+            word_pos: u128::from_le_bytes(self.take()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    fn checkpoints() -> Vec<Checkpoint> {
+        random::seed(21);
+        let rng = random::state();
+        random::seed(22);
+        let loader_rng = random::state();
+
+        let base = Checkpoint {
+            epoch: 3,
+            step: 42,
+            params: vec![0.5, -1.25, f64::MIN_POSITIVE, 1e300],
+            buffers: Vec::new(),
+            optimizer: OptimizerState::Stateless,
+            rng,
+            loader_rng: None,
+            scheduler_epoch: None,
+        };
+        vec![
+            base.clone(),
+            Checkpoint {
+                buffers: vec![0.1, 0.2],
+                optimizer: OptimizerState::Sgd {
+                    lr: 0.1,
+                    momentum: 0.9,
+                    velocity: vec![0.01, -0.02, 0.0, 0.5],
+                },
+                loader_rng: Some(loader_rng),
+                ..base.clone()
+            },
+            Checkpoint {
+                optimizer: OptimizerState::Adam {
+                    lr: 0.001,
+                    beta1: 0.9,
+                    beta2: 0.999,
+                    eps: 1e-8,
+                    m: vec![0.1; 4],
+                    v: vec![0.2; 4],
+                    t: 42,
+                },
+                scheduler_epoch: Some(3),
+                ..base
+            },
+        ]
+    }
+
+    /// `bytes` with its checksum recomputed, so only the change under test
+    /// is wrong with it.
+    fn resealed(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.truncate(bytes.len() - 4);
+        let checksum = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        let err = Checkpoint::from_bytes(bytes).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for checkpoint in checkpoints() {
+            let bytes = checkpoint.to_bytes();
+            assert_eq!(Checkpoint::from_bytes(&bytes).unwrap(), checkpoint);
+        }
+    }
+
+    #[test]
+    fn corrupted_checkpoints_are_rejected() {
+        let bytes = checkpoints().remove(1).to_bytes();
+
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        assert_eq!(error(&flipped), "checkpoint checksum mismatch");
+
+        assert_eq!(
+            error(&bytes[..bytes.len() - 1]),
+            "checkpoint checksum mismatch"
+        );
+        let mut cut = bytes[..bytes.len() - 12].to_vec();
+        cut.extend_from_slice(&[0; 4]);
+        assert_eq!(error(&resealed(cut)), "truncated checkpoint");
+
+        let mut longer = bytes.clone();
+        longer.insert(bytes.len() - 4, 0);
+        assert_eq!(error(&resealed(longer)), "trailing bytes in checkpoint");
+
+        assert_eq!(error(b"not a checkpoint at all"), "not a checkpoint file");
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let mut bytes = checkpoints().remove(0).to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(error(&resealed(bytes)), "unsupported checkpoint version 2");
+    }
+}
@@ -0,0 +1,519 @@
+use rand::Rng;
+
+use crate::{engine::*, init::ParamInit, module::Module, random};
+
+// Sequences are passed as flat rows, channel after channel: a row of `c`
+// channels of length `n` holds channel `i` at `[i * n..(i + 1) * n]`. The
+// length is inferred from the width of the row, and outputs use the same
+// layout.
+
+/// A 1D convolution (cross-correlation, as in PyTorch) over sequences of
+/// `in_channels` channels, producing `out_channels` channels.
+///
+/// Inputs are zero-padded by `padding` on both ends, the taps of the kernel
+/// are `dilation` apart and the kernel moves by `stride`.
+pub struct Conv1d {
+    pub in_channels: usize,
+    pub out_channels: usize,
+    pub kernel_size: usize,
+    pub stride: usize,
+    pub padding: usize,
+    pub dilation: usize,
+    /// One filter per output channel, holding the kernels of every input
+    /// channel one after the other.
+    pub weight: Vec<Vec<Value>>,
+    pub bias: Vec<Value>,
+}
+
+impl Conv1d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize) -> Self {
+        random::with_rng(|rng| Conv1d::with_rng(in_channels, out_channels, kernel_size, rng))
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        rng: &mut R,
+    ) -> Self {
+        Conv1d::with_init(
+            in_channels,
+            out_channels,
+            kernel_size,
+            &ParamInit::default(),
+            rng,
+        )
+    }
+
+    /// Every output sees `in_channels * kernel_size` inputs, which is the
+    /// fan-in the schemes of `init` are given.
+    pub fn with_init<R: Rng + ?Sized>(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        init: &ParamInit,
+        rng: &mut R,
+    ) -> Self {
+        assert!(kernel_size > 0, "kernel size must be positive");
+        let fan_in = in_channels * kernel_size;
+        let fan_out = out_channels * kernel_size;
+        let weight = init
+            .weight
+            .matrix(out_channels, fan_in, fan_in, fan_out, rng)
+            .into_iter()
+            .map(|filter| {
+                filter
+                    .into_iter()
+                    .map(|w| Value::new(w, "weight"))
+                    .collect()
+            })
+            .collect();
+        let bias = init
+            .bias
+            .matrix(out_channels, 1, fan_in, fan_out, rng)
+            .into_iter()
+            .map(|b| Value::new(b[0], "bias"))
+            .collect();
+
+        Conv1d {
+            in_channels,
+            out_channels,
+            kernel_size,
+            stride: 1,
+            padding: 0,
+            dilation: 1,
+            weight,
+            bias,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn dilation(mut self, dilation: usize) -> Self {
+        assert!(dilation > 0, "dilation must be positive");
+        self.dilation = dilation;
+        self
+    }
+
+    /// The length of the output sequences for inputs of length `len`.
+    pub fn output_len(&self, len: usize) -> usize {
+        windows(
+            len,
+            self.kernel_size,
+            self.stride,
+            self.padding,
+            self.dilation,
+        )
+        .len()
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let len = sequence_len(inputs.len(), self.in_channels);
+        let taps = self.taps(len);
+
+        let mut outputs = Vec::with_capacity(self.out_channels * taps.len());
+        for (filter, bias) in self.weight.iter().zip(&self.bias) {
+            for window in &taps {
+                let output = window.iter().fold(bias.clone(), |acc, (w, x)| {
+                    acc + inputs[*x].clone() * filter[*w].clone()
+                });
+                outputs.push(output);
+            }
+        }
+        outputs
+    }
+
+    /// `call` on plain floats, without building a graph. Sums in the same
+    /// order as `call`, so the result is identical.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let len = sequence_len(inputs.len(), self.in_channels);
+        let taps = self.taps(len);
+
+        let mut outputs = Vec::with_capacity(self.out_channels * taps.len());
+        for (filter, bias) in self.weight.iter().zip(&self.bias) {
+            for window in &taps {
+                let output = window.iter().fold(bias.borrow().data, |acc, (w, x)| {
+                    acc + inputs[*x] * filter[*w].borrow().data
+                });
+                outputs.push(output);
+            }
+        }
+        outputs
+    }
+
+    /// The filters, then the biases.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.weight
+            .iter()
+            .flatten()
+            .chain(&self.bias)
+            .cloned()
+            .collect()
+    }
+
+    /// `parameters()` with their paths, `weight.{out}.{i}` and `bias.{out}`,
+    /// where `i` indexes the filter of output channel `out`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        let weight = self.weight.iter().enumerate().flat_map(|(o, filter)| {
+            filter
+                .iter()
+                .enumerate()
+                .map(move |(i, w)| (format!("weight.{}.{}", o, i), w.clone()))
+        });
+        let bias = self
+            .bias
+            .iter()
+            .enumerate()
+            .map(|(o, b)| (format!("bias.{}", o), b.clone()));
+        weight.chain(bias).collect()
+    }
+
+    /// For every output position, the `(filter index, input index)` pairs
+    /// it sums over, across all input channels. Taps in the padding are
+    /// left out, since they multiply zero.
+    fn taps(&self, len: usize) -> Vec<Vec<(usize, usize)>> {
+        windows(
+            len,
+            self.kernel_size,
+            self.stride,
+            self.padding,
+            self.dilation,
+        )
+        .into_iter()
+        .map(|window| {
+            (0..self.in_channels)
+                .flat_map(|c| {
+                    window
+                        .iter()
+                        .map(move |(j, t)| (c * self.kernel_size + j, c * len + t))
+                })
+                .collect()
+        })
+        .collect()
+    }
+}
+
+/// Takes the maximum of every window of `kernel_size` steps, channel by
+/// channel. The gradient goes to the largest input of each window.
+pub struct MaxPool1d {
+    pub channels: usize,
+    pub kernel_size: usize,
+    /// Defaults to `kernel_size`, so windows don't overlap.
+    pub stride: usize,
+}
+
+impl MaxPool1d {
+    pub fn new(channels: usize, kernel_size: usize) -> Self {
+        assert!(kernel_size > 0, "kernel size must be positive");
+        MaxPool1d {
+            channels,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        self
+    }
+
+    pub fn output_len(&self, len: usize) -> usize {
+        windows(len, self.kernel_size, self.stride, 0, 1).len()
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        pool(
+            inputs,
+            self.channels,
+            self.kernel_size,
+            self.stride,
+            |window| {
+                window
+                    .iter()
+                    .max_by(|a, b| a.borrow().data.total_cmp(&b.borrow().data))
+                    .cloned()
+                    .unwrap()
+            },
+        )
+    }
+
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        pool(
+            inputs,
+            self.channels,
+            self.kernel_size,
+            self.stride,
+            |window| window.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        )
+    }
+}
+
+/// Takes the mean of every window of `kernel_size` steps, channel by
+/// channel.
+pub struct AvgPool1d {
+    pub channels: usize,
+    pub kernel_size: usize,
+    /// Defaults to `kernel_size`, so windows don't overlap.
+    pub stride: usize,
+}
+
+impl AvgPool1d {
+    pub fn new(channels: usize, kernel_size: usize) -> Self {
+        assert!(kernel_size > 0, "kernel size must be positive");
+        AvgPool1d {
+            channels,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "stride must be positive");
+        self.stride = stride;
+        self
+    }
+
+    pub fn output_len(&self, len: usize) -> usize {
+        windows(len, self.kernel_size, self.stride, 0, 1).len()
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let scale = 1.0 / self.kernel_size as f64;
+        pool(
+            inputs,
+            self.channels,
+            self.kernel_size,
+            self.stride,
+            |window| window.iter().cloned().sum::<Value>() * scale,
+        )
+    }
+
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let scale = 1.0 / self.kernel_size as f64;
+        pool(
+            inputs,
+            self.channels,
+            self.kernel_size,
+            self.stride,
+            |window| window.iter().sum::<f64>() * scale,
+        )
+    }
+}
+
+impl Module for Conv1d {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        Conv1d::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Conv1d::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        Conv1d::named_parameters(self)
+    }
+}
+
+impl Module for MaxPool1d {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        MaxPool1d::predict(self, inputs)
+    }
+}
+
+impl Module for AvgPool1d {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        AvgPool1d::predict(self, inputs)
+    }
+}
+
+/// The length of the sequences in a row of `width` values.
+fn sequence_len(width: usize, channels: usize) -> usize {
+    assert!(
+        channels > 0 && width.is_multiple_of(channels),
+        "a row of {} values does not split into {} channels",
+        width,
+        channels
+    );
+    width / channels
+}
+
+/// For every position of a kernel sliding over a sequence of `len` steps
+/// padded by `padding` on both ends, the `(tap, step)` pairs that fall
+/// inside the sequence.
+fn windows(
+    len: usize,
+    kernel_size: usize,
+    stride: usize,
+    padding: usize,
+    dilation: usize,
+) -> Vec<Vec<(usize, usize)>> {
+    let span = dilation * (kernel_size - 1) + 1;
+    let padded = len + 2 * padding;
+    assert!(
+        padded >= span,
+        "kernel spans {} steps but the padded input has {}",
+        span,
+        padded
+    );
+
+    (0..=(padded - span) / stride)
+        .map(|i| {
+            (0..kernel_size)
+                .filter_map(|j| {
+                    let t = (i * stride + j * dilation).checked_sub(padding)?;
+                    (t < len).then_some((j, t))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Applies `reduce` to every window of every channel.
+fn pool<T: Clone, F: Fn(&[T]) -> T>(
+    inputs: &[T],
+    channels: usize,
+    kernel_size: usize,
+    stride: usize,
+    reduce: F,
+) -> Vec<T> {
+    let len = sequence_len(inputs.len(), channels);
+    let windows = windows(len, kernel_size, stride, 0, 1);
+
+    inputs
+        .chunks(len.max(1))
+        .flat_map(|channel| {
+            windows
+                .iter()
+                .map(|window| {
+                    let values = window
+                        .iter()
+                        .map(|(_, t)| channel[*t].clone())
+                        .collect::<Vec<T>>();
+                    reduce(&values)
+                })
+                .collect::<Vec<T>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::assert_gradients;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    fn leaves(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|x| Value::new(*x, "x")).collect()
+    }
+
+    fn data(values: &[Value]) -> Vec<f64> {
+        values.iter().map(|v| v.borrow().data).collect()
+    }
+
+    /// A weighted sum of `values`, so every output gets a different
+    /// upstream gradient.
+    fn weighted(values: Vec<Value>) -> Value {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| v * (i as f64 * 0.3 - 1.0))
+            .sum()
+    }
+
+    /// The convolution written out directly over an explicitly padded
+    /// input.
+    fn reference(conv: &Conv1d, inputs: &[f64]) -> Vec<f64> {
+        let len = inputs.len() / conv.in_channels;
+        let padded = inputs
+            .chunks(len)
+            .map(|channel| {
+                let mut padded = vec![0.0; conv.padding];
+                padded.extend(channel);
+                padded.extend(vec![0.0; conv.padding]);
+                padded
+            })
+            .collect::<Vec<Vec<f64>>>();
+        let span = conv.dilation * (conv.kernel_size - 1) + 1;
+        let out_len = (len + 2 * conv.padding - span) / conv.stride + 1;
+
+        let mut outputs = Vec::new();
+        for (filter, bias) in conv.weight.iter().zip(&conv.bias) {
+            for i in 0..out_len {
+                let mut sum = bias.borrow().data;
+                for (c, channel) in padded.iter().enumerate() {
+                    for k in 0..conv.kernel_size {
+                        sum += filter[c * conv.kernel_size + k].borrow().data
+                            * channel[i * conv.stride + k * conv.dilation];
+                    }
+                }
+                outputs.push(sum);
+            }
+        }
+        outputs
+    }
+
+    #[test]
+    fn conv_matches_a_direct_convolution_and_finite_differences() {
+        let row = [
+            0.3, -1.2, 2.5, 0.9, -0.4, 1.7, 0.2, -2.1, 1.1, 0.6, -0.8, 1.3, 0.5, -1.5,
+        ];
+        for (stride, padding, dilation) in [(1, 0, 1), (2, 1, 1), (1, 2, 2), (3, 1, 2)] {
+            let mut rng = ChaCha12Rng::seed_from_u64(4);
+            let conv = Conv1d::with_rng(2, 3, 3, &mut rng)
+                .stride(stride)
+                .padding(padding)
+                .dilation(dilation);
+            let x = leaves(&row);
+
+            let outputs = conv.call(&x);
+            assert_eq!(outputs.len(), 3 * conv.output_len(7));
+            assert_eq!(data(&outputs), conv.predict(&row));
+            for (y, expected) in data(&outputs).iter().zip(reference(&conv, &row)) {
+                assert!((y - expected).abs() < 1e-12);
+            }
+
+            let mut inputs = conv.parameters();
+            inputs.extend(x.iter().cloned());
+            assert_gradients(&inputs, || weighted(conv.call(&x)));
+        }
+    }
+
+    #[test]
+    fn pools_match_finite_differences() {
+        let row = [0.3, -1.2, 2.5, 0.9, -0.4, 1.7, 0.2, -2.1, 1.1, 0.6];
+        for stride in [1, 2] {
+            let x = leaves(&row);
+
+            let max = MaxPool1d::new(2, 3).stride(stride);
+            assert_eq!(data(&max.call(&x)), max.predict(&row));
+            assert_gradients(&x, || weighted(max.call(&x)));
+
+            let avg = AvgPool1d::new(2, 3).stride(stride);
+            assert_eq!(data(&avg.call(&x)), avg.predict(&row));
+            assert_gradients(&x, || weighted(avg.call(&x)));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{engine::*, random::RngState};
+
+/// Indexed access to samples, each a row of features and a row of targets.
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A dataset held in memory as rows of features and targets.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDataset {
+    pub xs: Vec<Vec<f64>>,
+    pub ys: Vec<Vec<f64>>,
+}
+
+impl InMemoryDataset {
+    pub fn new(xs: Vec<Vec<f64>>, ys: Vec<Vec<f64>>) -> Self {
+        assert_eq!(xs.len(), ys.len(), "xs and ys differ in length");
+        InMemoryDataset { xs, ys }
+    }
+
+    /// Copies every sample of `dataset` into memory.
+    pub fn collect(dataset: &dyn Dataset) -> Self {
+        let (xs, ys) = (0..dataset.len()).map(|i| dataset.get(i)).unzip();
+        InMemoryDataset { xs, ys }
+    }
+
+    /// Every input row as `Value` leaves, ready for `MLP::call`.
+    pub fn inputs(&self) -> Vec<Vec<Value>> {
+        self.xs.iter().map(|x| to_values(x)).collect()
+    }
+}
+
+impl Dataset for InMemoryDataset {
+    fn len(&self) -> usize {
+        self.xs.len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (self.xs[index].clone(), self.ys[index].clone())
+    }
+}
+
+/// A mini-batch of raw rows. Inputs only become `Value` leaves when
+/// `inputs` is called.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub xs: Vec<Vec<f64>>,
+    pub ys: Vec<Vec<f64>>,
+}
+
+impl Batch {
+    pub fn len(&self) -> usize {
+        self.xs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.xs.is_empty()
+    }
+
+    pub fn inputs(&self) -> Vec<Vec<Value>> {
+        self.xs.iter().map(|x| to_values(x)).collect()
+    }
+}
+
+/// Wraps a row of features as constant inputs, which get no gradient.
+pub fn to_values(row: &[f64]) -> Vec<Value> {
+    row.iter().map(|x| Value::constant(*x, "x")).collect()
+}
+
+/// Splits a dataset into mini-batches, optionally reshuffling the sample
+/// order on every pass with a seeded RNG.
+pub struct DataLoader<'a> {
+    pub dataset: &'a dyn Dataset,
+    pub batch_size: usize,
+    pub drop_last: bool,
+    rng: Option<ChaCha12Rng>,
+}
+
+impl<'a> DataLoader<'a> {
+    pub fn new(dataset: &'a dyn Dataset, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size must be at least 1");
+        DataLoader {
+            dataset,
+            batch_size,
+            drop_last: false,
+            rng: None,
+        }
+    }
+
+    /// Shuffles the samples before every pass. The same seed gives the same
+    /// sequence of orders.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.rng = Some(ChaCha12Rng::seed_from_u64(seed));
+        self
+    }
+
+    /// The position of the shuffling RNG, if shuffling.
+    pub fn rng_state(&self) -> Option<RngState> {
+        self.rng.as_ref().map(RngState::of)
+    }
+
+    pub fn set_rng_state(&mut self, state: Option<&RngState>) {
+        self.rng = state.map(RngState::rng);
+    }
+
+    /// Skips the last batch when it is smaller than `batch_size`.
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// Number of batches in one pass.
+    pub fn len(&self) -> usize {
+        if self.drop_last {
+            self.dataset.len() / self.batch_size
+        } else {
+            self.dataset.len().div_ceil(self.batch_size)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// One pass over the dataset. Samples are fetched as each batch is
+    /// yielded.
+    pub fn iter(&mut self) -> Batches<'a> {
+        let mut order = (0..self.dataset.len()).collect::<Vec<usize>>();
+        if let Some(rng) = &mut self.rng {
+            order.shuffle(rng);
+        }
+        if self.drop_last {
+            order.truncate(self.len() * self.batch_size);
+        }
+
+        Batches {
+            dataset: self.dataset,
+            order,
+            batch_size: self.batch_size,
+            pos: 0,
+        }
+    }
+}
+
+pub struct Batches<'a> {
+    dataset: &'a dyn Dataset,
+    order: Vec<usize>,
+    batch_size: usize,
+    pos: usize,
+}
+
+impl Iterator for Batches<'_> {
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Batch> {
+        if self.pos >= self.order.len() {
+            return None;
+        }
+
+        let end = (self.pos + self.batch_size).min(self.order.len());
+        let (xs, ys) = self.order[self.pos..end]
+            .iter()
+            .map(|i| self.dataset.get(*i))
+            .unzip();
+        self.pos = end;
+
+        Some(Batch { xs, ys })
+    }
+}
@@ -0,0 +1,282 @@
+use std::f64::consts::PI;
+
+use rand::{
+    distributions::{Distribution, Uniform},
+    seq::SliceRandom,
+    SeedableRng,
+};
+use rand_chacha::ChaCha12Rng;
+use rand_distr::Normal;
+
+use crate::data::InMemoryDataset;
+
+// Toy datasets for experiments. Every generator is seeded and returns the
+// samples in shuffled order. Classification targets are class indices.
+//
+// The generators use `ChaCha12Rng` rather than `StdRng`, which may switch
+// algorithms in a new rand release, so a seed keeps giving the same samples.
+
+/// Two interleaving half circles.
+pub fn make_moons(n_samples: usize, noise: f64, seed: u64) -> InMemoryDataset {
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let n_outer = n_samples / 2;
+    let n_inner = n_samples - n_outer;
+
+    let mut samples = Vec::with_capacity(n_samples);
+    for t in linspace(0.0, PI, n_outer, true) {
+        samples.push((vec![t.cos(), t.sin()], 0.0));
+    }
+    for t in linspace(0.0, PI, n_inner, true) {
+        samples.push((vec![1.0 - t.cos(), 0.5 - t.sin()], 1.0));
+    }
+
+    finish(samples, noise, &mut rng)
+}
+
+/// A small circle inside a large one. `factor` is the ratio of their radii.
+pub fn make_circles(n_samples: usize, noise: f64, factor: f64, seed: u64) -> InMemoryDataset {
+    assert!(
+        (0.0..1.0).contains(&factor),
+        "factor must be in [0, 1), got {}",
+        factor
+    );
+
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let n_outer = n_samples / 2;
+    let n_inner = n_samples - n_outer;
+
+    let mut samples = Vec::with_capacity(n_samples);
+    for t in linspace(0.0, 2.0 * PI, n_outer, false) {
+        samples.push((vec![t.cos(), t.sin()], 0.0));
+    }
+    for t in linspace(0.0, 2.0 * PI, n_inner, false) {
+        samples.push((vec![factor * t.cos(), factor * t.sin()], 1.0));
+    }
+
+    finish(samples, noise, &mut rng)
+}
+
+/// `n_classes` intertwined spiral arms of `n_per_class` points each. The
+/// noise perturbs the angle, as in the CS231n version.
+pub fn make_spirals(
+    n_per_class: usize,
+    n_classes: usize,
+    noise: f64,
+    seed: u64,
+) -> InMemoryDataset {
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let angle_noise = normal(noise);
+
+    let mut samples = Vec::with_capacity(n_per_class * n_classes);
+    for class in 0..n_classes {
+        let radii = linspace(0.0, 1.0, n_per_class, true);
+        let start = class as f64 * 4.0;
+        let angles = linspace(start, start + 4.0, n_per_class, true);
+
+        for (r, t) in radii.into_iter().zip(angles) {
+            let t = t + angle_noise.sample(&mut rng);
+            samples.push((vec![r * t.sin(), r * t.cos()], class as f64));
+        }
+    }
+
+    finish(samples, 0.0, &mut rng)
+}
+
+/// Isotropic Gaussian clusters around `centers`, with samples spread evenly
+/// over the clusters.
+pub fn make_blobs(n_samples: usize, centers: &[Vec<f64>], std: f64, seed: u64) -> InMemoryDataset {
+    assert!(!centers.is_empty(), "make_blobs needs at least one center");
+
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let spread = normal(std);
+
+    let samples = (0..n_samples)
+        .map(|i| {
+            let class = i % centers.len();
+            let x = centers[class]
+                .iter()
+                .map(|c| c + spread.sample(&mut rng))
+                .collect();
+            (x, class as f64)
+        })
+        .collect();
+
+    finish(samples, 0.0, &mut rng)
+}
+
+/// Points drawn uniformly from [-1, 1]², labelled 1 when the signs of their
+/// coordinates differ.
+pub fn xor(n_samples: usize, noise: f64, seed: u64) -> InMemoryDataset {
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let between = Uniform::from(-1.0..1.0);
+
+    let samples = (0..n_samples)
+        .map(|_| {
+            let (a, b) = (between.sample(&mut rng), between.sample(&mut rng));
+            (vec![a, b], ((a > 0.0) != (b > 0.0)) as u8 as f64)
+        })
+        .collect();
+
+    finish(samples, noise, &mut rng)
+}
+
+/// `y = sin(x) + noise` for `x` drawn uniformly from [-π, π].
+pub fn make_sin(n_samples: usize, noise: f64, seed: u64) -> InMemoryDataset {
+    make_regression(n_samples, -PI..PI, noise, seed, f64::sin)
+}
+
+/// `y = coeffs[0] + coeffs[1] x + coeffs[2] x² + ... + noise` for `x`
+/// drawn uniformly from [-1, 1].
+pub fn make_polynomial(n_samples: usize, coeffs: &[f64], noise: f64, seed: u64) -> InMemoryDataset {
+    make_regression(n_samples, -1.0..1.0, noise, seed, |x| {
+        coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+    })
+}
+
+fn make_regression<F>(
+    n_samples: usize,
+    range: std::ops::Range<f64>,
+    noise: f64,
+    seed: u64,
+    f: F,
+) -> InMemoryDataset
+where
+    F: Fn(f64) -> f64,
+{
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let between = Uniform::from(range);
+    let target_noise = normal(noise);
+
+    let (xs, ys) = (0..n_samples)
+        .map(|_| {
+            let x = between.sample(&mut rng);
+            (vec![x], vec![f(x) + target_noise.sample(&mut rng)])
+        })
+        .unzip();
+
+    InMemoryDataset::new(xs, ys)
+}
+
+fn linspace(start: f64, end: f64, n: usize, endpoint: bool) -> Vec<f64> {
+    let steps = if endpoint { n.saturating_sub(1) } else { n };
+    let step = if steps == 0 {
+        0.0
+    } else {
+        (end - start) / steps as f64
+    };
+
+    (0..n).map(|i| start + step * i as f64).collect()
+}
+
+fn normal(std: f64) -> Normal<f64> {
+    Normal::new(0.0, std).expect("noise must be finite and non-negative")
+}
+
+/// Adds Gaussian noise to the features and shuffles the samples.
+fn finish(mut samples: Vec<(Vec<f64>, f64)>, noise: f64, rng: &mut ChaCha12Rng) -> InMemoryDataset {
+    let feature_noise = normal(noise);
+    for (x, _) in samples.iter_mut() {
+        for xi in x.iter_mut() {
+            *xi += feature_noise.sample(rng);
+        }
+    }
+
+    samples.shuffle(rng);
+
+    let (xs, ys) = samples.into_iter().map(|(x, y)| (x, vec![y])).unzip();
+    InMemoryDataset::new(xs, ys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class_counts(dataset: &InMemoryDataset, n_classes: usize) -> Vec<usize> {
+        let mut counts = vec![0; n_classes];
+        for y in dataset.ys.iter() {
+            counts[y[0] as usize] += 1;
+        }
+        counts
+    }
+
+    fn assert_shape(dataset: &InMemoryDataset, n_samples: usize, width: usize) {
+        assert_eq!(dataset.xs.len(), n_samples);
+        assert_eq!(dataset.ys.len(), n_samples);
+        assert!(dataset.xs.iter().all(|x| x.len() == width));
+        assert!(dataset.ys.iter().all(|y| y.len() == 1));
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_samples() {
+        let generators: [fn(u64) -> InMemoryDataset; 7] = [
+            |seed| make_moons(20, 0.1, seed),
+            |seed| make_circles(20, 0.1, 0.5, seed),
+            |seed| make_spirals(10, 3, 0.2, seed),
+            |seed| make_blobs(20, &[vec![0.0, 0.0], vec![3.0, 3.0]], 0.5, seed),
+            |seed| xor(20, 0.1, seed),
+            |seed| make_sin(20, 0.1, seed),
+            |seed| make_polynomial(20, &[1.0, -2.0, 0.5], 0.1, seed),
+        ];
+
+        for generate in generators {
+            let (a, b, other) = (generate(7), generate(7), generate(8));
+            assert_eq!(a.xs, b.xs);
+            assert_eq!(a.ys, b.ys);
+            assert_ne!(a.xs, other.xs);
+        }
+    }
+
+    #[test]
+    fn classification_sets_have_their_shape_and_balance() {
+        let moons = make_moons(11, 0.0, 1);
+        assert_shape(&moons, 11, 2);
+        assert_eq!(class_counts(&moons, 2), vec![5, 6]);
+        for (x, y) in moons.xs.iter().zip(&moons.ys) {
+            // the outer moon is the upper half of the unit circle and the
+            // inner one the same half circle flipped and shifted
+            let (cx, cy) = if y[0] == 0.0 { (0.0, 0.0) } else { (1.0, 0.5) };
+            assert!(((x[0] - cx).hypot(x[1] - cy) - 1.0).abs() < 1e-12);
+        }
+
+        let circles = make_circles(10, 0.0, 0.5, 1);
+        assert_shape(&circles, 10, 2);
+        assert_eq!(class_counts(&circles, 2), vec![5, 5]);
+        for (x, y) in circles.xs.iter().zip(&circles.ys) {
+            let radius = if y[0] == 0.0 { 1.0 } else { 0.5 };
+            assert!((x[0].hypot(x[1]) - radius).abs() < 1e-12);
+        }
+
+        let spirals = make_spirals(6, 3, 0.2, 1);
+        assert_shape(&spirals, 18, 2);
+        assert_eq!(class_counts(&spirals, 3), vec![6, 6, 6]);
+
+        let blobs = make_blobs(7, &[vec![0.0; 3], vec![5.0; 3], vec![-5.0; 3]], 0.1, 1);
+        assert_shape(&blobs, 7, 3);
+        assert_eq!(class_counts(&blobs, 3), vec![3, 2, 2]);
+
+        let xor = xor(50, 0.0, 1);
+        assert_shape(&xor, 50, 2);
+        for (x, y) in xor.xs.iter().zip(&xor.ys) {
+            assert_eq!(y[0], ((x[0] > 0.0) != (x[1] > 0.0)) as u8 as f64);
+        }
+        let ones = class_counts(&xor, 2)[1];
+        assert!((15..=35).contains(&ones), "{} of 50 labelled 1", ones);
+    }
+
+    #[test]
+    fn regression_sets_follow_their_function() {
+        let sin = make_sin(20, 0.0, 1);
+        assert_shape(&sin, 20, 1);
+        for (x, y) in sin.xs.iter().zip(&sin.ys) {
+            assert!((-PI..PI).contains(&x[0]));
+            assert_eq!(y[0], x[0].sin());
+        }
+
+        let polynomial = make_polynomial(20, &[1.0, -2.0, 0.5], 0.0, 1);
+        assert_shape(&polynomial, 20, 1);
+        for (x, y) in polynomial.xs.iter().zip(&polynomial.ys) {
+            let x = x[0];
+            assert!((y[0] - (1.0 - 2.0 * x + 0.5 * x * x)).abs() < 1e-12);
+        }
+    }
+}
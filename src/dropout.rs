@@ -0,0 +1,162 @@
+use std::cell::Cell;
+
+use rand::Rng;
+
+use crate::{engine::*, module::Module, random};
+
+/// Inverted dropout: in training mode every input is zeroed with probability
+/// `p` and the survivors are scaled by `1 / (1 - p)`, so the expected output
+/// equals the input and nothing needs rescaling at eval time. In eval mode
+/// it is the identity.
+///
+/// Masks are drawn from the crate-wide RNG, so `random::seed` makes them
+/// reproducible. A new dropout starts in training mode.
+#[derive(Debug, Clone)]
+pub struct Dropout {
+    pub p: f64,
+    training: Cell<bool>,
+}
+
+impl Dropout {
+    pub fn new(p: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "dropout probability must be in [0, 1), got {}",
+            p
+        );
+        Dropout {
+            p,
+            training: Cell::new(true),
+        }
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        if !self.is_training() || self.p == 0.0 {
+            return inputs.to_vec();
+        }
+
+        let scale = 1.0 / (1.0 - self.p);
+        random::with_rng(|rng| {
+            inputs
+                .iter()
+                .map(|x| {
+                    let keep = rng.gen::<f64>() >= self.p;
+                    x.clone() * if keep { scale } else { 0.0 }
+                })
+                .collect()
+        })
+    }
+
+    pub fn train(&self) {
+        self.training.set(true);
+    }
+
+    pub fn eval(&self) {
+        self.training.set(false);
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training.get()
+    }
+}
+
+impl Module for Dropout {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        inputs.to_vec()
+    }
+
+    fn train(&self) {
+        Dropout::train(self)
+    }
+
+    fn eval(&self) {
+        Dropout::eval(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(n: usize) -> Vec<Value> {
+        (0..n).map(|i| Value::new(1.0 + i as f64, "x")).collect()
+    }
+
+    fn data(values: &[Value]) -> Vec<f64> {
+        values.iter().map(|v| v.borrow().data).collect()
+    }
+
+    #[test]
+    fn eval_mode_is_the_identity() {
+        let dropout = Dropout::new(0.5);
+        dropout.eval();
+        assert!(!dropout.is_training());
+
+        let x = inputs(100);
+        assert_eq!(data(&dropout.call(&x)), data(&x));
+        assert_eq!(Module::predict(&dropout, &data(&x)), data(&x));
+
+        dropout.train();
+        assert_ne!(data(&dropout.call(&x)), data(&x));
+    }
+
+    #[test]
+    fn kept_activations_are_scaled_by_the_inverse_keep_rate() {
+        random::seed(1);
+        let dropout = Dropout::new(0.25);
+        let x = inputs(2000);
+        let y = dropout.call(&x);
+        y.iter().cloned().reduce(|a, b| a + b).unwrap().backward();
+
+        let mut kept = 0;
+        for (x, y) in x.iter().zip(&y) {
+            let (x, y) = (x.borrow(), y.borrow());
+            if y.data == 0.0 {
+                assert_eq!(x.grad, 0.0);
+            } else {
+                assert!(
+                    (y.data - x.data / 0.75).abs() < 1e-12,
+                    "{} from {}",
+                    y.data,
+                    x.data
+                );
+                assert!((x.grad - 1.0 / 0.75).abs() < 1e-12);
+                kept += 1;
+            }
+        }
+        let rate = kept as f64 / 2000.0;
+        assert!((rate - 0.75).abs() < 0.03, "kept {}", rate);
+    }
+
+    #[test]
+    fn zero_probability_keeps_everything_in_training() {
+        let dropout = Dropout::new(0.0);
+        let x = inputs(50);
+        assert_eq!(data(&dropout.call(&x)), data(&x));
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout probability must be in [0, 1), got 1")]
+    fn probability_one_is_rejected() {
+        Dropout::new(1.0);
+    }
+
+    #[test]
+    fn seeded_masks_are_reproducible() {
+        let dropout = Dropout::new(0.5);
+        let x = inputs(64);
+
+        random::seed(11);
+        let first = data(&dropout.call(&x));
+        random::seed(11);
+        let second = data(&dropout.call(&x));
+        let third = data(&dropout.call(&x));
+
+        assert_eq!(first, second);
+        assert_ne!(second, third);
+    }
+}
@@ -7,6 +7,13 @@ pub enum Op {
     Mul,
     Tanh,
     Exp,
+    Log,
+    ReLU,
+    Sigmoid,
+    Abs,
+    LogSumExp,
+    LogSoftmax,
+    Softmax,
 }
 
 pub struct ValueInfo {
@@ -15,6 +22,10 @@ pub struct ValueInfo {
     pub data: f64,
     pub grad: f64,
     pub prev: Vec<Value>,
+    /// Whether `backward` computes a gradient for this value. Leaves set it
+    /// themselves; every other value requires a gradient when one of its
+    /// inputs does.
+    pub requires_grad: bool,
     pub _backward: Option<Box<fn(value: &ValueInfo)>>,
     pub op: Option<Op>,
 }
@@ -77,13 +88,14 @@ impl ops::Add<Value> for Value {
             grad: 0.0,
             data: self.0.borrow().data + other.0.borrow().data,
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: Some(Op::Add),
         })));
 
         new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
-            value.prev[0].borrow_mut().grad += 1.0 * value.grad;
-            value.prev[1].borrow_mut().grad += 1.0 * value.grad;
+            value.prev[0].add_grad(value.grad);
+            value.prev[1].add_grad(value.grad);
         }));
 
         new_value
@@ -100,12 +112,13 @@ impl ops::Add<f64> for Value {
             grad: 0.0,
             data: self.0.borrow().data + rhs,
             prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
             _backward: None,
             op: Some(Op::Add),
         })));
 
         new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
-            value.prev[0].borrow_mut().grad += 1.0 * value.grad;
+            value.prev[0].add_grad(value.grad);
         }));
 
         new_value
@@ -133,16 +146,22 @@ impl ops::Neg for Value {
     type Output = Value;
 
     fn neg(self) -> Value {
-        let b_self = self.0.borrow();
-        return Value(Rc::new(RefCell::new(ValueInfo {
+        let new_value = Value(Rc::new(RefCell::new(ValueInfo {
             id: Uuid::new_v4(),
-            label: b_self.label.clone(),
-            grad: b_self.grad,
-            data: -b_self.data,
+            label: self.0.borrow().label.clone(),
+            grad: 0.0,
+            data: -self.0.borrow().data,
             prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
             _backward: None,
             op: None,
         })));
+
+        new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+            value.prev[0].add_grad(-value.grad);
+        }));
+
+        new_value
     }
 }
 
@@ -150,10 +169,19 @@ impl ops::Sub for Value {
     type Output = Value;
 
     fn sub(self, other: Value) -> Value {
-        return self + (-other);
+        self + (-other)
     }
 }
 
+impl ops::Sub<f64> for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: f64) -> Value {
+        self + (-rhs)
+    }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
 impl ops::Mul<f64> for Value {
     type Output = Value;
 
@@ -164,7 +192,8 @@ impl ops::Mul<f64> for Value {
             label: b_self.label.clone(),
             grad: 0.0,
             data: self.0.borrow().data * rhs,
-            prev: vec![self.clone()],
+            prev: vec![self.clone(), Value::constant(rhs, "")],
+            requires_grad: self.requires_grad(),
             _backward: None,
             op: Some(Op::Mul),
         })));
@@ -173,14 +202,15 @@ impl ops::Mul<f64> for Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * value.grad;
-            value.prev[1].borrow_mut().grad += data_1 * value.grad;
+            value.prev[0].add_grad(data_2 * value.grad);
+            value.prev[1].add_grad(data_1 * value.grad);
         }));
 
         new_value
     }
 }
 
+#[allow(clippy::suspicious_arithmetic_impl)]
 impl ops::Mul<Value> for Value {
     type Output = Value;
 
@@ -191,6 +221,7 @@ impl ops::Mul<Value> for Value {
             grad: 0.0,
             data: self.0.borrow().data * other.0.borrow().data,
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: Some(Op::Mul),
         })));
@@ -199,8 +230,8 @@ impl ops::Mul<Value> for Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * value.grad;
-            value.prev[1].borrow_mut().grad += data_1 * value.grad;
+            value.prev[0].add_grad(data_2 * value.grad);
+            value.prev[1].add_grad(data_1 * value.grad);
         }));
 
         new_value
@@ -216,6 +247,7 @@ impl ops::Mul<Value> for f64 {
     }
 }
 
+#[allow(clippy::suspicious_arithmetic_impl)]
 impl ops::Div for Value {
     type Output = Value;
 
@@ -226,6 +258,7 @@ impl ops::Div for Value {
             grad: 0.0,
             data: self.0.borrow().data * other.0.borrow().data.powi(-1),
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: None,
         })));
@@ -234,8 +267,8 @@ impl ops::Div for Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * value.grad;
-            value.prev[1].borrow_mut().grad += data_1 * value.grad;
+            value.prev[0].add_grad(data_2.powi(-1) * value.grad);
+            value.prev[1].add_grad(-data_1 * data_2.powi(-2) * value.grad);
         }));
 
         new_value
@@ -250,11 +283,29 @@ impl Value {
             grad: 0.0,
             data: value,
             prev: Vec::new(),
+            requires_grad: true,
             _backward: None,
             op: None,
         })))
     }
 
+    /// A leaf that never gets a gradient, e.g. an input or a target.
+    pub fn constant(value: f64, label: &str) -> Value {
+        let value = Value::new(value, label);
+        value.set_requires_grad(false);
+        value
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.0.borrow().requires_grad
+    }
+
+    /// Marks a leaf as trainable or frozen. Values computed from it earlier
+    /// keep the flag they were created with.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.0.borrow_mut().requires_grad = requires_grad;
+    }
+
     pub fn set_label(&self, label: &str) {
         self.0.borrow_mut().label = label.to_string();
     }
@@ -266,13 +317,14 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data + other.0.borrow().data,
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: Some(Op::Add),
         })));
 
         new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
-            value.prev[0].borrow_mut().grad += 1.0 * value.grad;
-            value.prev[1].borrow_mut().grad += 1.0 * value.grad;
+            value.prev[0].add_grad(value.grad);
+            value.prev[1].add_grad(value.grad);
         }));
 
         new_value
@@ -285,6 +337,7 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data * other.0.borrow().data,
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: Some(Op::Mul),
         })));
@@ -293,8 +346,8 @@ impl Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * value.grad;
-            value.prev[1].borrow_mut().grad += data_1 * value.grad;
+            value.prev[0].add_grad(data_2 * value.grad);
+            value.prev[1].add_grad(data_1 * value.grad);
         }));
 
         new_value
@@ -307,6 +360,7 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data * other.0.borrow().data.powi(-1),
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: None,
         })));
@@ -315,8 +369,8 @@ impl Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * value.grad;
-            value.prev[1].borrow_mut().grad += data_1 * value.grad;
+            value.prev[0].add_grad(data_2.powi(-1) * value.grad);
+            value.prev[1].add_grad(-data_1 * data_2.powi(-2) * value.grad);
         }));
 
         new_value
@@ -329,6 +383,7 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data.powf(other.0.borrow().data),
             prev: vec![self.clone(), other.clone()],
+            requires_grad: self.requires_grad() || other.requires_grad(),
             _backward: None,
             op: None,
         })));
@@ -337,7 +392,7 @@ impl Value {
             let data_1 = value.prev[0].borrow().data;
             let data_2 = value.prev[1].borrow().data;
 
-            value.prev[0].borrow_mut().grad += data_2 * data_1.powf(data_2 - 1.0) * value.grad;
+            value.prev[0].add_grad(data_2 * data_1.powf(data_2 - 1.0) * value.grad);
         }));
 
         new_value
@@ -350,12 +405,13 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data.exp(),
             prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
             _backward: None,
             op: Some(Op::Exp),
         })));
 
         new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
-            value.prev[0].borrow_mut().grad += value.data * value.grad;
+            value.prev[0].add_grad(value.data * value.grad);
         }));
 
         new_value
@@ -368,17 +424,122 @@ impl Value {
             grad: 0.0,
             data: self.0.borrow().data.tanh(),
             prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
             _backward: None,
             op: Some(Op::Tanh),
         })));
 
         new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
-            value.prev[0].borrow_mut().grad += (1.0 - value.data.powi(2)) * value.grad;
+            value.prev[0].add_grad((1.0 - value.data.powi(2)) * value.grad);
         }));
 
         new_value
     }
 
+    pub fn _log(&self, label: &str) -> Value {
+        let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+            id: Uuid::new_v4(),
+            label: label.to_string(),
+            grad: 0.0,
+            data: self.0.borrow().data.ln(),
+            prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
+            _backward: None,
+            op: Some(Op::Log),
+        })));
+
+        new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+            let data = value.prev[0].borrow().data;
+
+            value.prev[0].add_grad(data.powi(-1) * value.grad);
+        }));
+
+        new_value
+    }
+
+    pub fn _relu(&self, label: &str) -> Value {
+        let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+            id: Uuid::new_v4(),
+            label: label.to_string(),
+            grad: 0.0,
+            data: self.0.borrow().data.max(0.0),
+            prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
+            _backward: None,
+            op: Some(Op::ReLU),
+        })));
+
+        new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+            if value.data > 0.0 {
+                value.prev[0].add_grad(value.grad);
+            }
+        }));
+
+        new_value
+    }
+
+    pub fn _sigmoid(&self, label: &str) -> Value {
+        let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+            id: Uuid::new_v4(),
+            label: label.to_string(),
+            grad: 0.0,
+            data: 1.0 / (1.0 + (-self.0.borrow().data).exp()),
+            prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
+            _backward: None,
+            op: Some(Op::Sigmoid),
+        })));
+
+        new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+            value.prev[0].add_grad(value.data * (1.0 - value.data) * value.grad);
+        }));
+
+        new_value
+    }
+
+    pub fn _abs(&self, label: &str) -> Value {
+        let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+            id: Uuid::new_v4(),
+            label: label.to_string(),
+            grad: 0.0,
+            data: self.0.borrow().data.abs(),
+            prev: vec![self.clone()],
+            requires_grad: self.requires_grad(),
+            _backward: None,
+            op: Some(Op::Abs),
+        })));
+
+        new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+            let data = value.prev[0].borrow().data;
+
+            // subgradient of |x| at 0 is taken to be 0
+            let sign = if data > 0.0 {
+                1.0
+            } else if data < 0.0 {
+                -1.0
+            } else {
+                0.0
+            };
+
+            value.prev[0].add_grad(sign * value.grad);
+        }));
+
+        new_value
+    }
+
+    /// Adds `grad` to the gradient of this value if it requires one. Every
+    /// `_backward` goes through it, so frozen values and constants are never
+    /// written to.
+    fn add_grad(&self, grad: f64) {
+        let mut info = self.0.borrow_mut();
+        if info.requires_grad {
+            info.grad += grad;
+        }
+    }
+
+    /// Backpropagates from this value. Subgraphs that don't require a
+    /// gradient are not visited, and the gradients of values that don't
+    /// require one are left as they were.
     pub fn backward(&self) {
         let mut stack = Vec::<Value>::new();
         let mut visited = HashSet::<Uuid>::new();
@@ -387,12 +548,14 @@ impl Value {
             if !visited.contains(&value.borrow().id) {
                 visited.insert(value.borrow().id);
 
+                if !value.requires_grad() {
+                    return;
+                }
+
                 for prev in value.borrow().prev.iter() {
                     build_topo(prev, stack, visited)
                 }
                 stack.push(value.clone());
-            } else {
-                return;
             }
         }
 
@@ -415,3 +578,185 @@ impl Value {
         self.0.borrow()
     }
 }
+
+/// `log(sum(exp(x)))` over `values` as a single node. The max is subtracted
+/// before exponentiating so large inputs don't overflow.
+pub fn logsumexp(values: &[Value]) -> Value {
+    assert!(!values.is_empty(), "logsumexp of an empty slice");
+
+    let max = values
+        .iter()
+        .map(|v| v.borrow().data)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let sum = values
+        .iter()
+        .map(|v| (v.borrow().data - max).exp())
+        .sum::<f64>();
+
+    let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+        id: Uuid::new_v4(),
+        label: "logsumexp".to_string(),
+        grad: 0.0,
+        data: max + sum.ln(),
+        prev: values.to_vec(),
+        requires_grad: values.iter().any(Value::requires_grad),
+        _backward: None,
+        op: Some(Op::LogSumExp),
+    })));
+
+    new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+        // d lse / d x_i = softmax(x)_i = exp(x_i - lse)
+        for prev in value.prev.iter().filter(|prev| prev.requires_grad()) {
+            let softmax = (prev.borrow().data - value.data).exp();
+            prev.add_grad(softmax * value.grad);
+        }
+    }));
+
+    new_value
+}
+
+/// `x_i - logsumexp(x)` for every element. All outputs share one
+/// `logsumexp` node, so backward stays linear in the number of inputs.
+pub fn log_softmax(values: &[Value]) -> Vec<Value> {
+    let lse = logsumexp(values);
+
+    values
+        .iter()
+        .map(|x| {
+            let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+                id: Uuid::new_v4(),
+                label: "log_softmax".to_string(),
+                grad: 0.0,
+                data: x.borrow().data - lse.borrow().data,
+                prev: vec![x.clone(), lse.clone()],
+                requires_grad: lse.requires_grad(),
+                _backward: None,
+                op: Some(Op::LogSoftmax),
+            })));
+
+            new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+                value.prev[0].add_grad(value.grad);
+                value.prev[1].add_grad(-value.grad);
+            }));
+
+            new_value
+        })
+        .collect()
+}
+
+/// `exp(x_i - logsumexp(x))` for every element, sharing one `logsumexp`
+/// node like `log_softmax`.
+pub fn softmax(values: &[Value]) -> Vec<Value> {
+    let lse = logsumexp(values);
+
+    values
+        .iter()
+        .map(|x| {
+            let new_value = Value(Rc::new(RefCell::new(ValueInfo {
+                id: Uuid::new_v4(),
+                label: "softmax".to_string(),
+                grad: 0.0,
+                data: (x.borrow().data - lse.borrow().data).exp(),
+                prev: vec![x.clone(), lse.clone()],
+                requires_grad: lse.requires_grad(),
+                _backward: None,
+                op: Some(Op::Softmax),
+            })));
+
+            new_value.borrow_mut()._backward = Some(Box::new(|value: &ValueInfo| {
+                // s_i = exp(x_i - lse), so ds_i/dx_i = s_i and ds_i/dlse = -s_i
+                value.prev[0].add_grad(value.data * value.grad);
+                value.prev[1].add_grad(-value.data * value.grad);
+            }));
+
+            new_value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Checks the gradients `backward` leaves in `inputs` against central
+    /// differences of `f`, which builds a scalar from them.
+    pub(crate) fn assert_gradients(inputs: &[Value], f: impl Fn() -> Value) {
+        for x in inputs {
+            x.0.borrow_mut().grad = 0.0;
+        }
+        f().backward();
+
+        let h = 1e-6;
+        for (i, x) in inputs.iter().enumerate() {
+            let data = x.borrow().data;
+            x.0.borrow_mut().data = data + h;
+            let up = f().borrow().data;
+            x.0.borrow_mut().data = data - h;
+            let down = f().borrow().data;
+            x.0.borrow_mut().data = data;
+
+            let expected = (up - down) / (2.0 * h);
+            let actual = x.borrow().grad;
+            assert!(
+                (actual - expected).abs() < 1e-6 * expected.abs().max(1.0),
+                "gradient {} is {}, expected {}",
+                i,
+                actual,
+                expected
+            );
+        }
+    }
+
+    /// A weighted sum of `values`, so every output gets a different
+    /// upstream gradient.
+    fn weighted(values: Vec<Value>) -> Value {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| v * (i as f64 + 1.0))
+            .sum()
+    }
+
+    fn leaves(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|x| Value::new(*x, "x")).collect()
+    }
+
+    #[test]
+    fn fused_softmax_nodes_match_finite_differences() {
+        for data in [vec![0.5, -1.0, 2.0, 0.0], vec![1000.0, 999.0, 1001.5]] {
+            let x = leaves(&data);
+            assert_gradients(&x, || logsumexp(&x) * 2.0);
+            assert_gradients(&x, || weighted(log_softmax(&x)));
+            assert_gradients(&x, || weighted(softmax(&x)));
+        }
+    }
+
+    #[test]
+    fn fused_softmax_nodes_are_stable() {
+        let x = leaves(&[1000.0, 1000.0]);
+        assert!((logsumexp(&x).borrow().data - (1000.0 + 2f64.ln())).abs() < 1e-9);
+
+        let probs = softmax(&x);
+        for p in probs.iter() {
+            assert!((p.borrow().data - 0.5).abs() < 1e-12);
+        }
+        let total = probs.into_iter().sum::<Value>();
+        assert!((total.borrow().data - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn values_that_dont_require_grad_are_not_written() {
+        let w = Value::new(2.0, "w");
+        let frozen = Value::new(3.0, "frozen");
+        frozen.set_requires_grad(false);
+        let x = Value::constant(-1.5, "x");
+
+        let out =
+            (w.clone() * frozen.clone() + x.clone())._tanh("t") - x._exp("e") / frozen.clone();
+        out.backward();
+
+        assert_ne!(w.borrow().grad, 0.0);
+        assert_eq!(frozen.borrow().grad, 0.0);
+        assert_eq!(x.borrow().grad, 0.0);
+    }
+}
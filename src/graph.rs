@@ -12,6 +12,22 @@ type Graph = DiGraph<String, String>;
 #[allow(dead_code)]
 type NodeHashMap = HashMap<Uuid, (NodeIndex, Value)>;
 
+fn op_label(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Mul => "*",
+        Op::Tanh => "tanh",
+        Op::Exp => "exp",
+        Op::Log => "log",
+        Op::ReLU => "relu",
+        Op::Sigmoid => "sigmoid",
+        Op::Abs => "abs",
+        Op::LogSumExp => "logsumexp",
+        Op::LogSoftmax => "log_softmax",
+        Op::Softmax => "softmax",
+    }
+}
+
 #[allow(dead_code)]
 fn recursive_build(
     graph: &mut Graph,
@@ -19,7 +35,7 @@ fn recursive_build(
     // op_hash_set: &mut OpHashSet,
     node: &Value,
 ) -> NodeIndex {
-    if let Some(ref node_props) = node_hash_map.get(&node.borrow().id) {
+    if let Some(node_props) = node_hash_map.get(&node.borrow().id) {
         return node_props.0;
     }
 
@@ -35,13 +51,7 @@ fn recursive_build(
     for child in node.borrow().prev.iter() {
         let child_index = recursive_build(graph, node_hash_map, child);
 
-        let op = match child.borrow().op {
-            Some(Op::Add) => "+",
-            Some(Op::Mul) => "*",
-            Some(Op::Tanh) => "tanh",
-            Some(Op::Exp) => "exp",
-            None => "",
-        };
+        let op = child.borrow().op.as_ref().map_or("", op_label);
 
         graph.add_edge(child_index, node_index, op.to_string());
     }
@@ -66,12 +76,7 @@ fn build_graph(root_node: &Value) -> Graph {
             let node = node_index.1.borrow();
 
             if let Some(op) = &node.op {
-                let op_str = match op {
-                    Op::Add => "+",
-                    Op::Mul => "*",
-                    Op::Tanh => "tanh",
-                    Op::Exp => "exp",
-                };
+                let op_str = op_label(op);
 
                 let op_index = graph.add_node(op_str.to_string());
 
@@ -92,8 +97,8 @@ fn build_graph(root_node: &Value) -> Graph {
             let edge1 = graph.edge_endpoints(first).unwrap();
             let edge2 = graph.edge_endpoints(second).unwrap();
 
-            let (_source1, target1) = (edge1.clone().0.index(), edge1.clone().1.index());
-            let (_source2, target2) = (edge2.clone().0.index(), edge2.clone().1.index());
+            let (_source1, target1) = (edge1.0.index(), edge1.1.index());
+            let (_source2, target2) = (edge2.0.index(), edge2.1.index());
 
             if target1 == target2 {
                 let op_index = op_hash_map.get(&edge1.1).unwrap();
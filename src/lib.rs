@@ -0,0 +1,25 @@
+pub mod activation;
+pub mod checkpoint;
+pub mod conv;
+pub mod data;
+pub mod datasets;
+pub mod dropout;
+pub mod engine;
+pub mod graph;
+pub mod init;
+pub mod loss;
+pub mod metrics;
+pub mod mlp;
+pub mod module;
+pub mod neuron;
+pub mod norm;
+pub mod onnx;
+pub mod optim;
+pub mod preprocess;
+pub mod random;
+pub mod scheduler;
+pub mod skip;
+pub mod split;
+pub mod tabular;
+pub mod trainer;
+pub mod weights;
@@ -0,0 +1,424 @@
+use crate::engine::*;
+
+/// How the per-sample losses are combined. `Mean` and `Sum` yield a single
+/// `Value`, `None` returns one `Value` per sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduction {
+    Mean,
+    Sum,
+    None,
+}
+
+/// The losses in this module, so a loss can be picked at runtime and handed
+/// around (e.g. to a training loop).
+///
+/// `preds` holds one row per sample, as returned by `MLP::call`, and `targets`
+/// the matching rows of targets. For `CrossEntropy` each target row is a
+/// single class index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+    Mse,
+    Mae,
+    Huber(f64),
+    BceWithLogits,
+    Bce,
+    Hinge,
+    CrossEntropy,
+}
+
+impl Loss {
+    pub fn call(&self, preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Value {
+        self.call_with(preds, targets, Reduction::Mean, None)
+            .remove(0)
+    }
+
+    pub fn call_with(
+        &self,
+        preds: &[Vec<Value>],
+        targets: &[Vec<f64>],
+        reduction: Reduction,
+        weights: Option<&[f64]>,
+    ) -> Vec<Value> {
+        match *self {
+            Loss::Mse => mse(preds, targets, reduction, weights),
+            Loss::Mae => mae(preds, targets, reduction, weights),
+            Loss::Huber(delta) => huber(preds, targets, delta, reduction, weights),
+            Loss::BceWithLogits => bce_with_logits(preds, targets, reduction, weights),
+            Loss::Bce => bce(preds, targets, reduction, weights),
+            Loss::Hinge => hinge(preds, targets, reduction, weights),
+            Loss::CrossEntropy => cross_entropy(preds, targets, reduction, weights),
+        }
+    }
+}
+
+/// Mean squared error.
+pub fn mse(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    let losses = elementwise(preds, targets, |y_out, y_gt| {
+        let diff = y_out.clone() - y_gt;
+        diff.clone() * diff
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Mean absolute error.
+pub fn mae(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    let losses = elementwise(preds, targets, |y_out, y_gt| {
+        (y_out.clone() - y_gt)._abs("abs_err")
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Huber loss, also known as smooth L1 when `delta` is 1: quadratic for
+/// errors up to `delta` and linear beyond.
+pub fn huber(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    delta: f64,
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    assert!(delta > 0.0, "huber delta must be positive, got {}", delta);
+
+    let losses = elementwise(preds, targets, |y_out, y_gt| {
+        let diff = y_out.clone() - y_gt;
+        let abs = diff._abs("abs_err");
+
+        if abs.borrow().data <= delta {
+            (diff.clone() * diff) * 0.5
+        } else {
+            (abs - 0.5 * delta) * delta
+        }
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Binary cross-entropy on raw logits, with targets in {0, 1}.
+///
+/// Uses `max(z, 0) - z * y + log(1 + exp(-|z|))`, which never exponentiates a
+/// positive number.
+pub fn bce_with_logits(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    let losses = elementwise(preds, targets, |z, y| {
+        let softplus = ((-z._abs(""))._exp("") + 1.0)._log("");
+        z._relu("") - z.clone() * y + softplus
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Binary cross-entropy on probabilities in (0, 1), with targets in {0, 1}.
+pub fn bce(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    // keeps log() finite when a prediction saturates at exactly 0 or 1
+    const EPS: f64 = 1e-12;
+
+    let losses = elementwise(preds, targets, |p, y| {
+        let log_p = (p.clone() + EPS)._log("");
+        let log_not_p = (-p.clone() + (1.0 + EPS))._log("");
+        -(log_p * y + log_not_p * (1.0 - y))
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Hinge (SVM max-margin) loss, with targets in {-1, 1}.
+pub fn hinge(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    let losses = elementwise(preds, targets, |score, y| {
+        (-(score.clone() * y) + 1.0)._relu("hinge")
+    });
+    reduce(losses, reduction, weights)
+}
+
+/// Categorical cross-entropy on raw logits. Each target row holds the index
+/// of the correct class.
+pub fn cross_entropy(
+    preds: &[Vec<Value>],
+    targets: &[Vec<f64>],
+    reduction: Reduction,
+    weights: Option<&[f64]>,
+) -> Vec<Value> {
+    assert_eq!(
+        preds.len(),
+        targets.len(),
+        "preds and targets differ in length"
+    );
+
+    let losses = preds
+        .iter()
+        .zip(targets)
+        .map(|(logits, target)| {
+            let class = target[0] as usize;
+            assert!(
+                class < logits.len(),
+                "class {} out of range for {} logits",
+                class,
+                logits.len()
+            );
+
+            logsumexp(logits) - logits[class].clone()
+        })
+        .collect();
+    reduce(losses, reduction, weights)
+}
+
+/// Applies `f` to every (prediction, target) pair and averages over the
+/// outputs of each sample, giving one loss per sample. An empty row has a
+/// loss of 0.
+fn elementwise<F>(preds: &[Vec<Value>], targets: &[Vec<f64>], f: F) -> Vec<Value>
+where
+    F: Fn(&Value, f64) -> Value,
+{
+    assert_eq!(
+        preds.len(),
+        targets.len(),
+        "preds and targets differ in length"
+    );
+
+    preds
+        .iter()
+        .zip(targets)
+        .map(|(y_out, y_gt)| {
+            assert_eq!(
+                y_out.len(),
+                y_gt.len(),
+                "prediction and target rows differ in width"
+            );
+
+            let n = y_out.len() as f64;
+            let sum = total(y_out.iter().zip(y_gt).map(|(p, t)| f(p, *t)).collect());
+
+            if n <= 1.0 {
+                sum
+            } else {
+                sum * n.recip()
+            }
+        })
+        .collect()
+}
+
+/// Weights the per-sample losses and combines them. A weighted mean divides
+/// by the sum of the weights.
+///
+/// Over zero samples, or weights that sum to zero, `Sum` and `Mean` give a
+/// constant 0 and `None` gives no losses.
+pub fn reduce(losses: Vec<Value>, reduction: Reduction, weights: Option<&[f64]>) -> Vec<Value> {
+    let (losses, total_weight) = match weights {
+        Some(weights) => {
+            assert_eq!(
+                losses.len(),
+                weights.len(),
+                "one weight per sample is required"
+            );
+            let weighted = losses
+                .into_iter()
+                .zip(weights)
+                .map(|(loss, w)| loss * *w)
+                .collect::<Vec<Value>>();
+            (weighted, weights.iter().sum::<f64>())
+        }
+        None => {
+            let n = losses.len() as f64;
+            (losses, n)
+        }
+    };
+
+    match reduction {
+        Reduction::None => losses,
+        Reduction::Sum => vec![total(losses)],
+        Reduction::Mean if total_weight == 0.0 => vec![Value::constant(0.0, "loss")],
+        Reduction::Mean => vec![total(losses) * total_weight.recip()],
+    }
+}
+
+/// The sum of `values`, or a constant 0 if there are none.
+fn total(values: Vec<Value>) -> Value {
+    if values.is_empty() {
+        Value::constant(0.0, "loss")
+    } else {
+        values.into_iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::assert_gradients;
+
+    fn rows(data: &[&[f64]]) -> Vec<Vec<Value>> {
+        data.iter()
+            .map(|row| row.iter().map(|x| Value::new(*x, "pred")).collect())
+            .collect()
+    }
+
+    fn targets(data: &[&[f64]]) -> Vec<Vec<f64>> {
+        data.iter().map(|row| row.to_vec()).collect()
+    }
+
+    fn data(losses: &[Value]) -> Vec<f64> {
+        losses.iter().map(|l| l.borrow().data).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "got {}, expected {}",
+            actual,
+            expected
+        );
+    }
+
+    /// A loss, predictions, targets and the expected mean loss.
+    type Case = (
+        Loss,
+        &'static [&'static [f64]],
+        &'static [&'static [f64]],
+        f64,
+    );
+
+    #[test]
+    fn losses_match_hand_computed_values() {
+        let cases: [Case; 7] = [
+            // (1 + 4) / 2 and 1
+            (
+                Loss::Mse,
+                &[&[1.0, 2.0], &[0.0]],
+                &[&[0.0, 4.0], &[1.0]],
+                1.75,
+            ),
+            // (1 + 2) / 2 and 1
+            (
+                Loss::Mae,
+                &[&[1.0, 2.0], &[0.0]],
+                &[&[0.0, 4.0], &[1.0]],
+                1.25,
+            ),
+            // 0.5 * 0.5^2 and 1 * (3 - 0.5)
+            (
+                Loss::Huber(1.0),
+                &[&[0.5], &[3.0]],
+                &[&[0.0], &[0.0]],
+                1.3125,
+            ),
+            (
+                Loss::Bce,
+                &[&[0.8], &[0.25]],
+                &[&[1.0], &[0.0]],
+                (-(0.8f64.ln()) - 0.75f64.ln()) / 2.0,
+            ),
+            (
+                Loss::BceWithLogits,
+                &[&[0.0], &[2.0]],
+                &[&[1.0], &[0.0]],
+                (2f64.ln() + (1.0 + 2f64.exp()).ln()) / 2.0,
+            ),
+            (Loss::Hinge, &[&[0.5], &[-2.0]], &[&[1.0], &[-1.0]], 0.25),
+            (
+                Loss::CrossEntropy,
+                &[&[1.0, 2.0, 3.0]],
+                &[&[2.0]],
+                (1f64.exp() + 2f64.exp() + 3f64.exp()).ln() - 3.0,
+            ),
+        ];
+
+        for (loss, preds, ys, expected) in cases {
+            assert_close(
+                loss.call(&rows(preds), &targets(ys)).borrow().data,
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn reductions_and_weights() {
+        let preds = rows(&[&[1.0, 2.0], &[0.0]]);
+        let ys = targets(&[&[0.0, 4.0], &[1.0]]);
+
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::None, None)),
+            vec![2.5, 1.0]
+        );
+        assert_eq!(data(&mse(&preds, &ys, Reduction::Sum, None)), vec![3.5]);
+        assert_eq!(data(&mse(&preds, &ys, Reduction::Mean, None)), vec![1.75]);
+
+        let weights = [1.0, 3.0];
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::None, Some(&weights))),
+            vec![2.5, 3.0]
+        );
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::Sum, Some(&weights))),
+            vec![5.5]
+        );
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::Mean, Some(&weights))),
+            vec![1.375]
+        );
+    }
+
+    #[test]
+    fn empty_inputs_have_zero_loss() {
+        for reduction in [Reduction::Mean, Reduction::Sum] {
+            assert_eq!(data(&mse(&[], &[], reduction, None)), vec![0.0]);
+        }
+        assert!(mse(&[], &[], Reduction::None, None).is_empty());
+
+        let preds = rows(&[&[], &[2.0]]);
+        let ys = targets(&[&[], &[0.0]]);
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::None, None)),
+            vec![0.0, 4.0]
+        );
+        assert_eq!(
+            data(&mse(&preds, &ys, Reduction::Mean, Some(&[0.0, 0.0]))),
+            vec![0.0]
+        );
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let losses = [
+            Loss::Mse,
+            Loss::Mae,
+            Loss::Huber(1.0),
+            Loss::Huber(0.25),
+            Loss::Bce,
+            Loss::BceWithLogits,
+            Loss::Hinge,
+        ];
+        for loss in losses {
+            let preds = rows(&[&[0.3, 0.6], &[0.9, 0.2]]);
+            let ys = targets(&[&[1.0, 0.0], &[0.0, 1.0]]);
+            let inputs = preds.iter().flatten().cloned().collect::<Vec<Value>>();
+            assert_gradients(&inputs, || {
+                loss.call_with(&preds, &ys, Reduction::Mean, Some(&[2.0, 0.5]))
+                    .remove(0)
+            });
+        }
+
+        let logits = rows(&[&[0.3, -1.2, 2.0], &[1.5, 0.1, -0.4]]);
+        let ys = targets(&[&[2.0], &[1.0]]);
+        let inputs = logits.iter().flatten().cloned().collect::<Vec<Value>>();
+        assert_gradients(&inputs, || Loss::CrossEntropy.call(&logits, &ys));
+    }
+}
@@ -1,11 +1,16 @@
-mod engine;
-mod graph;
-mod mlp;
-mod neuron;
-
-use engine::Value;
-use mlp::Layer;
-use neuron::Neuron;
+use rusty_micrograd::data::{to_values, DataLoader, InMemoryDataset};
+use rusty_micrograd::datasets::make_moons;
+use rusty_micrograd::engine::Value;
+use rusty_micrograd::graph::create_graphviz;
+use rusty_micrograd::loss::Loss;
+use rusty_micrograd::metrics::{Average, Metric};
+use rusty_micrograd::mlp::{Layer, MLP};
+use rusty_micrograd::module::Module;
+use rusty_micrograd::neuron::Neuron;
+use rusty_micrograd::optim::Sgd;
+use rusty_micrograd::random;
+use rusty_micrograd::split::train_val_test_split;
+use rusty_micrograd::trainer::{Callback, Control, Logs, Trainer};
 
 use plotters::prelude::*;
 
@@ -14,9 +19,6 @@ use petgraph::data::FromElements;
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{NodeIndex, UnGraph};
 
-use crate::graph::create_graphviz;
-use crate::mlp::MLP;
-
 fn main() {
     let mut args = std::env::args();
 
@@ -32,6 +34,7 @@ fn main() {
             "layer_test" => layer_test(),
             "mlp_test" => mlp_test(),
             "binary_classifier" => binary_classifier(),
+            "moons" => moons(),
             _ => println!("Invalid argument"),
         }
     } else {
@@ -40,82 +43,34 @@ fn main() {
 }
 
 fn binary_classifier() {
-    let x1 = vec![
-        Value::new(2.0, "x1"),
-        Value::new(3.0, "x2"),
-        Value::new(-1.0, "x3"),
-    ];
-    let x2 = vec![
-        Value::new(3.0, "x1"),
-        Value::new(-1.0, "x2"),
-        Value::new(0.5, "x3"),
-    ];
-    let x3 = vec![
-        Value::new(0.5, "x1"),
-        Value::new(1.0, "x2"),
-        Value::new(1.0, "x3"),
-    ];
-    let x4 = vec![
-        Value::new(1.0, "x1"),
-        Value::new(1.0, "x2"),
-        Value::new(-1.0, "x3"),
-    ];
-
-    let xs = vec![x1, x2, x3, x4];
+    let xs = [
+        [2.0, 3.0, -1.0],
+        [3.0, -1.0, 0.5],
+        [0.5, 1.0, 1.0],
+        [1.0, 1.0, -1.0],
+    ]
+    .map(|x| x.to_vec());
 
     // Desired Targets
-    let ys = vec![
-        Value::new(1.0, "y1"),
-        Value::new(-1.0, "y2"),
-        Value::new(-1.0, "y3"),
-        Value::new(1.0, "y4"),
-    ];
+    let ys = [[1.0], [-1.0], [-1.0], [1.0]].map(|y| y.to_vec());
 
     let mlp = MLP::new(3, vec![4, 4, 1]);
 
-    for _ in 0..500 {
-        let ypred = xs
-            .iter()
-            .flat_map(|x| mlp.call(x).into_iter())
-            .collect::<Vec<Value>>();
+    let dataset = InMemoryDataset::new(xs.to_vec(), ys.to_vec());
+    let mut loader = DataLoader::new(&dataset, xs.len());
 
-        let loss = ypred
-            .iter()
-            .zip(ys.iter())
-            .map(|(y_out, y_gt)| {
-                let diff = y_out.to_owned() - y_gt.to_owned();
-                (y_out.to_owned() - y_gt.to_owned()) * diff
-            })
-            .sum::<Value>();
-        // / Value::new(ys.len() as f64, "n");
-
-        for p in mlp.parameters() {
-            p.0.borrow_mut().grad = 0.0;
-        }
-
-        loss.backward();
+    let mut trainer = Trainer::new(&mlp, Loss::Mse, Sgd::new(0.075))
+        .epochs(500)
+        .metrics(vec![Metric::Accuracy, Metric::F1(Average::Binary)]);
+    trainer.fit(&mut loader, None, &mut [&mut PrintLogs]);
 
-        for p in mlp.parameters() {
-            let grad = p.0.borrow().grad;
-            p.0.borrow_mut().data += -0.075 * grad;
-        }
-
-        println!("loss: {:?}", loss.0.borrow().data);
-    }
+    println!("ys: {:?}", ys.iter().flatten().collect::<Vec<&f64>>());
+    let ypred = xs
+        .iter()
+        .map(|x| mlp.call(&to_values(x)))
+        .collect::<Vec<Vec<Value>>>();
 
-    println!(
-        "ys: {:?}",
-        ys.iter().map(|v| v.0.borrow().data).collect::<Vec<f64>>()
-    );
-    let ypred = xs.iter().flat_map(|x| mlp.call(x)).collect::<Vec<Value>>();
-
-    println!(
-        "mlp params: {:?}",
-        mlp.parameters()
-            .iter()
-            .map(|v| v.0.borrow().data)
-            .collect::<Vec<f64>>()
-    );
+    println!("{}", mlp.summary());
 
     println!("ypred length: {:?}", ypred.len());
 
@@ -123,24 +78,49 @@ fn binary_classifier() {
         "ypred: {:?}",
         ypred
             .iter()
+            .flatten()
             .map(|v| v.0.borrow().data)
             .collect::<Vec<f64>>()
     );
 
-    let loss = ypred
-        .iter()
-        .zip(ys.iter())
-        .map(|(y_out, y_gt)| {
-            let diff = y_out.to_owned() - y_gt.to_owned();
-            (y_out.to_owned() - y_gt.to_owned()) * diff
-        })
-        .sum::<Value>();
+    let loss = Loss::Mse.call(&ypred, &ys);
 
     println!("loss: {:?}", loss.0.borrow().data);
 
     create_graphviz(&loss, "./plots/binary_classifier.dot")
 }
 
+fn moons() {
+    random::seed(1337);
+    let mut dataset = make_moons(100, 0.1, 1337);
+
+    // tanh outputs live in [-1, 1], so train against ±1 labels
+    for y in dataset.ys.iter_mut() {
+        y[0] = 2.0 * y[0] - 1.0;
+    }
+
+    let split = train_val_test_split(&dataset, 0.15, 0.15, true, 1337);
+
+    let mlp = MLP::new(2, vec![16, 16, 1]);
+    let mut loader = DataLoader::new(&split.train, 10).shuffle(1337);
+
+    let mut trainer = Trainer::new(&mlp, Loss::Hinge, Sgd::with_momentum(0.05, 0.9))
+        .epochs(50)
+        .metrics(vec![Metric::Accuracy]);
+    trainer.fit(&mut loader, Some(&split.val), &mut [&mut PrintLogs]);
+
+    println!("test: {:?}", trainer.evaluate(&split.test));
+}
+
+struct PrintLogs;
+
+impl Callback for PrintLogs {
+    fn on_epoch_end(&mut self, epoch: usize, logs: &Logs, _model: &dyn Module) -> Control {
+        println!("epoch {}: {:?}", epoch, logs);
+        Control::Continue
+    }
+}
+
 fn mlp_test() {
     let x = vec![
         Value::new(2.0, "a"),
@@ -183,7 +163,7 @@ fn impl_ops_example() {
     let w1 = Value::new(-3.0, "w1");
     let w2 = Value::new(1.0, "w2");
 
-    let b = Value::new(6.8813735870195432, "b");
+    let b = Value::new(6.881_373_587_019_543, "b");
 
     let x1w1 = x1 * w1;
     x1w1.set_label("x1*w1");
@@ -211,7 +191,7 @@ fn second_example() {
     let w1 = Value::new(-3.0, "w1");
     let w2 = Value::new(1.0, "w2");
 
-    let b = Value::new(6.8813735870195432, "b");
+    let b = Value::new(6.881_373_587_019_543, "b");
 
     let x1w1 = x1.mul(&w1, "x1*w1");
     let x2w2 = x2.mul(&w2, "x2*w2");
@@ -249,8 +229,7 @@ fn first_example() {
 
 #[warn(dead_code)]
 pub fn test_function(x: i32) -> i32 {
-    let x = 3 * x.pow(2) + 4 * x + 5;
-    x
+    3 * x.pow(2) + 4 * x + 5
 }
 
 #[warn(dead_code)]
@@ -289,9 +268,9 @@ pub fn plotters_example() -> Result<(), Box<dyn std::error::Error>> {
         5,
         &RED,
         &|c, s, st| {
-            return EmptyElement::at(c)    // We want to construct a composed element on-the-fly
+            EmptyElement::at(c)    // We want to construct a composed element on-the-fly
             + Circle::new((0,0),s,st.filled()) // At this point, the new pixel coordinate is established
-            + Text::new(format!("{:?}", c), (10, 0), ("sans-serif", 10).into_font());
+            + Text::new(format!("{:?}", c), (10, 0), ("sans-serif", 10).into_font())
         },
     ))?;
     root.present()?;
@@ -300,7 +279,7 @@ pub fn plotters_example() -> Result<(), Box<dyn std::error::Error>> {
 
 #[warn(dead_code)]
 pub fn petgraph_example() {
-    let g = UnGraph::<i32, ()>::from_edges(&[(1, 2), (2, 3), (3, 4), (1, 4)]);
+    let g = UnGraph::<i32, ()>::from_edges([(1, 2), (2, 3), (3, 4), (1, 4)]);
 
     // Find the shortest path from `1` to `4` using `1` as the cost for every edge.
     let node_map = dijkstra(&g, 1.into(), Some(4.into()), |_| 1);
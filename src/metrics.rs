@@ -0,0 +1,563 @@
+use std::cmp::Ordering;
+
+use crate::activation::Activation;
+
+/// How per-class scores are combined for multi-class precision, recall and
+/// F1. `Binary` only scores class 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Average {
+    Binary,
+    Macro,
+    Micro,
+}
+
+/// What the classification metrics take model outputs to be, which depends
+/// on the output activation of the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Outputs {
+    /// Raw scores from a linear or tanh head, e.g. for `Loss::BceWithLogits`,
+    /// `Loss::CrossEntropy` or `Loss::Hinge`.
+    #[default]
+    Logits,
+    /// Probabilities from a sigmoid or softmax head, e.g. for `Loss::Bce`.
+    Probabilities,
+}
+
+/// A metric that can be monitored by `Trainer`. `compute` takes model
+/// outputs and targets, one row per sample.
+///
+/// Single-output rows are turned into classes by thresholding at 0 for
+/// `Outputs::Logits`, which fits both tanh outputs trained against ±1 targets
+/// and raw logits trained against {0, 1}, and at 0.5 for
+/// `Outputs::Probabilities`. Wider rows use the argmax, and their targets may
+/// be either one-hot rows or a single class index.
+///
+/// `LogLoss` and `RocAuc` work on probabilities, so logits go through a
+/// sigmoid for single outputs and a softmax for wider rows first. `RocAuc`
+/// scores class 1, and takes rows of at most two outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Accuracy,
+    Precision(Average),
+    Recall(Average),
+    F1(Average),
+    RocAuc,
+    LogLoss,
+    Mse,
+    Mae,
+    R2,
+}
+
+impl Metric {
+    pub fn name(&self) -> String {
+        let with_average = |name: &str, average: &Average| match average {
+            Average::Binary => name.to_string(),
+            Average::Macro => format!("{}_macro", name),
+            Average::Micro => format!("{}_micro", name),
+        };
+
+        match self {
+            Metric::Accuracy => "accuracy".to_string(),
+            Metric::Precision(average) => with_average("precision", average),
+            Metric::Recall(average) => with_average("recall", average),
+            Metric::F1(average) => with_average("f1", average),
+            Metric::RocAuc => "roc_auc".to_string(),
+            Metric::LogLoss => "log_loss".to_string(),
+            Metric::Mse => "mse".to_string(),
+            Metric::Mae => "mae".to_string(),
+            Metric::R2 => "r2".to_string(),
+        }
+    }
+
+    /// `compute_with` for `Outputs::Logits`.
+    pub fn compute(&self, preds: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
+        self.compute_with(Outputs::Logits, preds, targets)
+    }
+
+    pub fn compute_with(&self, outputs: Outputs, preds: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
+        assert_eq!(
+            preds.len(),
+            targets.len(),
+            "preds and targets differ in length"
+        );
+
+        let n_classes = preds.first().map_or(2, |row| row.len().max(2));
+        let y_pred = || to_labels_with(outputs, preds);
+        let y_true = || target_labels(targets, n_classes);
+
+        match *self {
+            Metric::Accuracy => accuracy(&y_true(), &y_pred()),
+            Metric::Precision(average) => precision(&y_true(), &y_pred(), n_classes, average),
+            Metric::Recall(average) => recall(&y_true(), &y_pred(), n_classes, average),
+            Metric::F1(average) => f1(&y_true(), &y_pred(), n_classes, average),
+            Metric::RocAuc => {
+                assert!(
+                    n_classes == 2,
+                    "roc_auc scores binary outputs, got rows of {}",
+                    n_classes
+                );
+                let scores = to_probs(outputs, preds)
+                    .iter()
+                    .map(|row| row[row.len() - 1])
+                    .collect::<Vec<f64>>();
+                roc_auc(&scores, &y_true())
+            }
+            Metric::LogLoss => log_loss(&to_probs(outputs, preds), &y_true()),
+            Metric::Mse => mse(preds, targets),
+            Metric::Mae => mae(preds, targets),
+            Metric::R2 => r2(preds, targets),
+        }
+    }
+}
+
+/// Decodes model outputs into class labels: a threshold at 0 for single
+/// outputs, the argmax otherwise.
+pub fn to_labels(preds: &[Vec<f64>]) -> Vec<usize> {
+    to_labels_with(Outputs::Logits, preds)
+}
+
+/// `to_labels` for either kind of output. Single probabilities are
+/// thresholded at 0.5.
+pub fn to_labels_with(outputs: Outputs, preds: &[Vec<f64>]) -> Vec<usize> {
+    let threshold = match outputs {
+        Outputs::Logits => 0.0,
+        Outputs::Probabilities => 0.5,
+    };
+    preds
+        .iter()
+        .map(|row| {
+            if row.len() == 1 {
+                (row[0] > threshold) as usize
+            } else {
+                argmax(row)
+            }
+        })
+        .collect()
+}
+
+/// Turns logits into probabilities: a sigmoid for single outputs, a softmax
+/// across wider rows. Probabilities are returned as they are.
+fn to_probs(outputs: Outputs, preds: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    if outputs == Outputs::Probabilities {
+        return preds.to_vec();
+    }
+    preds
+        .iter()
+        .map(|row| {
+            if row.len() == 1 {
+                vec![Activation::Sigmoid.apply(row[0])]
+            } else {
+                Activation::Softmax.apply_layer(row.clone())
+            }
+        })
+        .collect()
+}
+
+fn target_labels(targets: &[Vec<f64>], n_classes: usize) -> Vec<usize> {
+    targets
+        .iter()
+        .map(|row| match (row.len(), n_classes) {
+            (1, 2) => (row[0] > 0.0) as usize,
+            (1, _) => row[0] as usize,
+            _ => argmax(row),
+        })
+        .collect()
+}
+
+fn argmax(row: &[f64]) -> usize {
+    row.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))
+        .map_or(0, |(i, _)| i)
+}
+
+pub fn accuracy(y_true: &[usize], y_pred: &[usize]) -> f64 {
+    assert_eq!(y_true.len(), y_pred.len(), "label slices differ in length");
+
+    let correct = y_true.iter().zip(y_pred).filter(|(t, p)| t == p).count();
+    ratio(correct as f64, y_true.len() as f64)
+}
+
+/// `matrix[true][pred]` counts the samples of class `true` predicted as `pred`.
+pub fn confusion_matrix(y_true: &[usize], y_pred: &[usize], n_classes: usize) -> Vec<Vec<usize>> {
+    assert_eq!(y_true.len(), y_pred.len(), "label slices differ in length");
+
+    let mut matrix = vec![vec![0; n_classes]; n_classes];
+    for (t, p) in y_true.iter().zip(y_pred) {
+        matrix[*t][*p] += 1;
+    }
+    matrix
+}
+
+/// True positives, false positives and false negatives for every class.
+fn class_counts(y_true: &[usize], y_pred: &[usize], n_classes: usize) -> Vec<(f64, f64, f64)> {
+    let matrix = confusion_matrix(y_true, y_pred, n_classes);
+
+    (0..n_classes)
+        .map(|c| {
+            let tp = matrix[c][c] as f64;
+            let predicted = (0..n_classes).map(|t| matrix[t][c]).sum::<usize>() as f64;
+            let actual = matrix[c].iter().sum::<usize>() as f64;
+            (tp, predicted - tp, actual - tp)
+        })
+        .collect()
+}
+
+fn ratio(num: f64, den: f64) -> f64 {
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
+
+fn averaged<F>(
+    y_true: &[usize],
+    y_pred: &[usize],
+    n_classes: usize,
+    average: Average,
+    score: F,
+) -> f64
+where
+    F: Fn(f64, f64, f64) -> f64,
+{
+    let counts = class_counts(y_true, y_pred, n_classes);
+
+    match average {
+        Average::Binary => {
+            let (tp, fp, fn_) = counts[1];
+            score(tp, fp, fn_)
+        }
+        Average::Macro => {
+            counts
+                .iter()
+                .map(|(tp, fp, fn_)| score(*tp, *fp, *fn_))
+                .sum::<f64>()
+                / n_classes as f64
+        }
+        Average::Micro => {
+            let (tp, fp, fn_) = counts.iter().fold((0.0, 0.0, 0.0), |acc, c| {
+                (acc.0 + c.0, acc.1 + c.1, acc.2 + c.2)
+            });
+            score(tp, fp, fn_)
+        }
+    }
+}
+
+pub fn precision(y_true: &[usize], y_pred: &[usize], n_classes: usize, average: Average) -> f64 {
+    averaged(y_true, y_pred, n_classes, average, |tp, fp, _| {
+        ratio(tp, tp + fp)
+    })
+}
+
+pub fn recall(y_true: &[usize], y_pred: &[usize], n_classes: usize, average: Average) -> f64 {
+    averaged(y_true, y_pred, n_classes, average, |tp, _, fn_| {
+        ratio(tp, tp + fn_)
+    })
+}
+
+pub fn f1(y_true: &[usize], y_pred: &[usize], n_classes: usize, average: Average) -> f64 {
+    averaged(y_true, y_pred, n_classes, average, |tp, fp, fn_| {
+        ratio(2.0 * tp, 2.0 * tp + fp + fn_)
+    })
+}
+
+/// Area under the ROC curve for binary labels, from the rank statistic of the
+/// scores. Tied scores share their average rank.
+pub fn roc_auc(scores: &[f64], y_true: &[usize]) -> f64 {
+    assert_eq!(
+        scores.len(),
+        y_true.len(),
+        "scores and labels differ in length"
+    );
+
+    let mut order = (0..scores.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| {
+        scores[*a]
+            .partial_cmp(&scores[*b])
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut ranks = vec![0.0; scores.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && scores[order[j + 1]] == scores[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for k in i..=j {
+            ranks[order[k]] = rank;
+        }
+        i = j + 1;
+    }
+
+    let n_pos = y_true.iter().filter(|y| **y == 1).count() as f64;
+    let n_neg = y_true.len() as f64 - n_pos;
+    if n_pos == 0.0 || n_neg == 0.0 {
+        return f64::NAN;
+    }
+
+    let pos_rank_sum = ranks
+        .iter()
+        .zip(y_true)
+        .filter(|(_, y)| **y == 1)
+        .map(|(r, _)| r)
+        .sum::<f64>();
+
+    (pos_rank_sum - n_pos * (n_pos + 1.0) / 2.0) / (n_pos * n_neg)
+}
+
+/// Mean negative log-likelihood of the true labels. Single-output rows hold
+/// the probability of class 1, wider rows one probability per class.
+///
+/// Probabilities outside [0, 1] are rejected, since clamping them would
+/// hide that they are logits.
+pub fn log_loss(probs: &[Vec<f64>], y_true: &[usize]) -> f64 {
+    assert_eq!(
+        probs.len(),
+        y_true.len(),
+        "probs and labels differ in length"
+    );
+
+    assert!(
+        probs.iter().flatten().all(|p| (0.0..=1.0).contains(p)),
+        "log_loss takes probabilities in [0, 1]"
+    );
+
+    // keeps ln() finite for saturated predictions
+    const EPS: f64 = 1e-15;
+
+    let total = probs
+        .iter()
+        .zip(y_true)
+        .map(|(row, y)| {
+            let p = if row.len() == 1 {
+                if *y == 1 {
+                    row[0]
+                } else {
+                    1.0 - row[0]
+                }
+            } else {
+                row[*y]
+            };
+            -p.clamp(EPS, 1.0 - EPS).ln()
+        })
+        .sum::<f64>();
+
+    total / probs.len() as f64
+}
+
+/// Mean squared error over all outputs, 0 when there are none.
+pub fn mse(preds: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
+    let (sum, n) = flat_pairs(preds, targets)
+        .fold((0.0, 0), |(sum, n), (p, t)| (sum + (p - t).powi(2), n + 1));
+    ratio(sum, n as f64)
+}
+
+/// Mean absolute error over all outputs, 0 when there are none.
+pub fn mae(preds: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
+    let (sum, n) =
+        flat_pairs(preds, targets).fold((0.0, 0), |(sum, n), (p, t)| (sum + (p - t).abs(), n + 1));
+    ratio(sum, n as f64)
+}
+
+/// Coefficient of determination over all outputs.
+///
+/// When the targets are constant, or there are none, there is no variance to
+/// explain: a perfect fit scores 1 and anything else 0, as in scikit-learn.
+pub fn r2(preds: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
+    let (sum, n) = flat_pairs(preds, targets).fold((0.0, 0), |(sum, n), (_, t)| (sum + t, n + 1));
+    let mean = ratio(sum, n as f64);
+
+    let (ss_res, ss_tot) = flat_pairs(preds, targets).fold((0.0, 0.0), |(res, tot), (p, t)| {
+        (res + (t - p).powi(2), tot + (t - mean).powi(2))
+    });
+
+    if ss_tot > 0.0 {
+        1.0 - ss_res / ss_tot
+    } else if ss_res == 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn flat_pairs<'a>(
+    preds: &'a [Vec<f64>],
+    targets: &'a [Vec<f64>],
+) -> impl Iterator<Item = (f64, f64)> + 'a {
+    assert_eq!(
+        preds.len(),
+        targets.len(),
+        "preds and targets differ in length"
+    );
+
+    preds
+        .iter()
+        .zip(targets)
+        .flat_map(|(p, t)| p.iter().copied().zip(t.iter().copied()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_loss_metric_reads_single_outputs_as_logits() {
+        let preds = [vec![3.0], vec![-3.0]];
+        let targets = [vec![1.0], vec![0.0]];
+
+        let expected = (1.0 + (-3f64).exp()).ln();
+        let actual = Metric::LogLoss.compute(&preds, &targets);
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn log_loss_metric_reads_wide_rows_as_logits() {
+        let preds = [vec![2.0, 0.0, -1.0], vec![0.0, 0.0, 0.0]];
+        let targets = [vec![0.0], vec![2.0]];
+
+        let lse = (2f64.exp() + 1.0 + (-1f64).exp()).ln();
+        let expected = ((lse - 2.0) + 3f64.ln()) / 2.0;
+        let actual = Metric::LogLoss.compute(&preds, &targets);
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "probabilities in [0, 1]")]
+    fn log_loss_rejects_logits() {
+        log_loss(&[vec![3.0]], &[1]);
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// Rows whose argmax is `labels`.
+    fn one_hot(labels: &[usize], n_classes: usize) -> Vec<Vec<f64>> {
+        labels
+            .iter()
+            .map(|l| (0..n_classes).map(|c| (c == *l) as u8 as f64).collect())
+            .collect()
+    }
+
+    fn column(values: &[f64]) -> Vec<Vec<f64>> {
+        values.iter().map(|v| vec![*v]).collect()
+    }
+
+    #[test]
+    fn confusion_matrix_counts_true_by_pred() {
+        let matrix = confusion_matrix(&[0, 1, 2, 2, 1, 0], &[0, 2, 2, 2, 1, 1], 3);
+        assert_eq!(matrix, vec![vec![1, 1, 0], vec![0, 1, 1], vec![0, 0, 2]]);
+    }
+
+    #[test]
+    fn multi_class_metrics_match_hand_computed_values() {
+        // per class: precision 1, 1/2, 2/3; recall 1/2, 1/2, 1; f1 2/3, 1/2, 4/5
+        let preds = one_hot(&[0, 2, 2, 2, 1, 1], 3);
+        let targets = column(&[0.0, 1.0, 2.0, 2.0, 1.0, 0.0]);
+
+        let cases = [
+            (Metric::Accuracy, 4.0 / 6.0),
+            (Metric::Precision(Average::Macro), 13.0 / 18.0),
+            (Metric::Recall(Average::Macro), 2.0 / 3.0),
+            (Metric::F1(Average::Macro), 59.0 / 90.0),
+            (Metric::Precision(Average::Micro), 2.0 / 3.0),
+            (Metric::Recall(Average::Micro), 2.0 / 3.0),
+            (Metric::F1(Average::Micro), 2.0 / 3.0),
+        ];
+        for (metric, expected) in cases {
+            assert_close(metric.compute(&preds, &targets), expected);
+        }
+        assert_eq!(
+            Metric::Accuracy.compute(&preds, &one_hot(&[0, 1, 2, 2, 1, 0], 3)),
+            4.0 / 6.0
+        );
+    }
+
+    #[test]
+    fn binary_metrics_threshold_logits_and_probabilities() {
+        // both predict [1, 1, 0, 1, 1]: 2 true positives, 2 false positives
+        // and 1 false negative
+        let logits = column(&[2.0, 0.5, -1.0, 0.3, 0.1]);
+        let probs = column(&[0.9, 0.6, 0.2, 0.7, 0.55]);
+        let targets = column(&[1.0, 0.0, 1.0, 1.0, 0.0]);
+
+        for (outputs, preds) in [(Outputs::Logits, &logits), (Outputs::Probabilities, &probs)] {
+            let compute = |metric: Metric| metric.compute_with(outputs, preds, &targets);
+            assert_close(compute(Metric::Accuracy), 0.4);
+            assert_close(compute(Metric::Precision(Average::Binary)), 0.5);
+            assert_close(compute(Metric::Recall(Average::Binary)), 2.0 / 3.0);
+            assert_close(compute(Metric::F1(Average::Binary)), 4.0 / 7.0);
+        }
+    }
+
+    #[test]
+    fn sigmoid_heads_are_not_read_as_logits() {
+        let preds = column(&[0.9, 0.2]);
+        let targets = column(&[1.0, 0.0]);
+
+        assert_eq!(Metric::Accuracy.compute(&preds, &targets), 0.5);
+        assert_eq!(
+            Metric::Accuracy.compute_with(Outputs::Probabilities, &preds, &targets),
+            1.0
+        );
+        assert_close(
+            Metric::LogLoss.compute_with(Outputs::Probabilities, &preds, &targets),
+            -(0.9f64.ln() + 0.8f64.ln()) / 2.0,
+        );
+    }
+
+    #[test]
+    fn roc_auc_scores_the_positive_class() {
+        // 3 of the 4 (positive, negative) pairs are ranked correctly
+        let scores = [0.9, 0.2, 0.7, 0.4];
+        let targets = column(&[1.0, 0.0, 0.0, 1.0]);
+
+        assert_close(Metric::RocAuc.compute(&column(&scores), &targets), 0.75);
+
+        let logits = scores.iter().map(|s| vec![-s, *s]).collect::<Vec<_>>();
+        assert_close(Metric::RocAuc.compute(&logits, &targets), 0.75);
+
+        let probs = scores.iter().map(|s| vec![1.0 - s, *s]).collect::<Vec<_>>();
+        assert_close(
+            Metric::RocAuc.compute_with(Outputs::Probabilities, &probs, &targets),
+            0.75,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "roc_auc scores binary outputs")]
+    fn roc_auc_rejects_more_than_two_columns() {
+        Metric::RocAuc.compute(&one_hot(&[0, 1, 2], 3), &column(&[0.0, 1.0, 2.0]));
+    }
+
+    #[test]
+    fn regression_metrics_of_constant_and_empty_targets() {
+        let targets = column(&[2.0, 2.0]);
+        assert_eq!(r2(&column(&[2.0, 2.0]), &targets), 1.0);
+        assert_eq!(r2(&column(&[1.0, 3.0]), &targets), 0.0);
+        assert_close(r2(&column(&[1.0, 2.5]), &column(&[1.0, 3.0])), 0.875);
+
+        assert_eq!(mse(&[], &[]), 0.0);
+        assert_eq!(mae(&[], &[]), 0.0);
+        assert_eq!(r2(&[], &[]), 1.0);
+        assert_eq!(accuracy(&[], &[]), 0.0);
+    }
+}
@@ -1,18 +1,83 @@
-use crate::{engine::*, neuron::Neuron};
+use std::{
+    fmt, fs,
+    io::{self, ErrorKind},
+    ops::RangeBounds,
+    path::Path,
+};
 
+use crate::{
+    activation::Activation,
+    engine::*,
+    init::{Init, ParamInit},
+    module::Module,
+    neuron::Neuron,
+    preprocess::Pipeline,
+    random,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A fully connected layer. Put a `Dropout` after it in a `Sequential` to
+/// regularize it.
 pub struct Layer(pub Vec<Neuron>);
 
 impl Layer {
     pub fn new(nin: i32, nout: i32) -> Self {
-        let mut neurons = Vec::new();
-        for _ in 0..nout {
-            neurons.push(Neuron::new(nin, true));
-        }
+        random::with_rng(|rng| Layer::with_rng(nin, nout, rng))
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(nin: i32, nout: i32, rng: &mut R) -> Self {
+        Layer::with_init(nin, nout, &ParamInit::default(), rng)
+    }
+
+    /// Draws the weights and bias of one neuron after the other, so a seed
+    /// builds the same layer as `with_rng`. `Init::Orthogonal` weights are
+    /// drawn as one `nout` x `nin` matrix instead, before the biases, since
+    /// the scheme needs to see the whole layer.
+    pub fn with_init<R: Rng + ?Sized>(nin: i32, nout: i32, init: &ParamInit, rng: &mut R) -> Self {
+        let (nin, nout) = (nin as usize, nout as usize);
+        let params = if let Init::Orthogonal(_) = init.weight {
+            let weights = init.weight.matrix(nout, nin, nin, nout, rng);
+            let biases = init.bias.matrix(nout, 1, nin, nout, rng);
+            weights
+                .into_iter()
+                .zip(biases.into_iter().map(|b| b[0]))
+                .collect::<Vec<(Vec<f64>, f64)>>()
+        } else {
+            (0..nout)
+                .map(|_| {
+                    let w = init.weight.matrix(1, nin, nin, nout, rng).remove(0);
+                    let b = init.bias.matrix(1, 1, nin, nout, rng)[0][0];
+                    (w, b)
+                })
+                .collect()
+        };
+
+        let neurons = params
+            .into_iter()
+            .map(|(w, b)| Neuron::from_weights(w, b, Activation::Tanh))
+            .collect();
         Layer(neurons)
     }
 
-    pub fn call(&self, inputs: &Vec<Value>) -> Vec<Value> {
-        self.0.iter().map(|neuron| neuron.call(inputs)).collect()
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let outputs = self
+            .0
+            .iter()
+            .map(|neuron| neuron.call(inputs))
+            .collect::<Vec<Value>>();
+        self.activation().call_layer(outputs)
+    }
+
+    /// `call` on every sample of a batch, e.g. to feed a `BatchNorm1d`.
+    pub fn call_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        batch.iter().map(|x| self.call(x)).collect()
+    }
+
+    /// Evaluates the layer on plain floats.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let outputs = self.0.iter().map(|neuron| neuron.predict(inputs)).collect();
+        self.activation().apply_layer(outputs)
     }
 
     pub fn parameters(&self) -> Vec<Value> {
@@ -21,30 +86,644 @@ impl Layer {
             .flat_map(|neuron| neuron.parameters())
             .collect()
     }
+
+    /// `parameters()` with their paths, e.g. `neurons.3.w.2`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(j, neuron)| prefixed(&format!("neurons.{}", j), neuron.named_parameters()))
+            .collect()
+    }
+
+    /// Stops training the weights and biases of this layer.
+    pub fn freeze(&self) {
+        self.set_requires_grad(false);
+    }
+
+    pub fn unfreeze(&self) {
+        self.set_requires_grad(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.parameters().iter().all(|p| !p.requires_grad())
+    }
+
+    fn set_requires_grad(&self, requires_grad: bool) {
+        for p in self.parameters() {
+            p.set_requires_grad(requires_grad);
+        }
+    }
+
+    pub fn nin(&self) -> usize {
+        self.0.first().map_or(0, |neuron| neuron.0.len())
+    }
+
+    pub fn nout(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The weights as an `nout` x `nin` matrix, one row per neuron.
+    pub fn weights(&self) -> Vec<Vec<f64>> {
+        self.0
+            .iter()
+            .map(|neuron| neuron.0.iter().map(|w| w.borrow().data).collect())
+            .collect()
+    }
+
+    pub fn biases(&self) -> Vec<f64> {
+        self.0.iter().map(|neuron| neuron.1.borrow().data).collect()
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.0.first().map_or(Activation::Linear, |neuron| neuron.2)
+    }
+
+    pub fn set_activation(&mut self, activation: Activation) {
+        for neuron in self.0.iter_mut() {
+            neuron.2 = activation;
+        }
+    }
 }
 
+pub(crate) fn prefixed(prefix: &str, named: Vec<(String, Value)>) -> Vec<(String, Value)> {
+    named
+        .into_iter()
+        .map(|(name, value)| (format!("{}.{}", prefix, name), value))
+        .collect()
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct MLP(pub Vec<Layer>);
 
 impl MLP {
     pub fn new(nin: i32, nouts: Vec<i32>) -> Self {
+        random::with_rng(|rng| MLP::with_rng(nin, nouts, rng))
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(nin: i32, nouts: Vec<i32>, rng: &mut R) -> Self {
+        MLP::with_init(nin, nouts, &ParamInit::default(), rng)
+    }
+
+    /// Hidden layers use tanh and the last layer is linear, as in micrograd.
+    /// Change them with `activations` or `output_activation`.
+    pub fn with_init<R: Rng + ?Sized>(
+        nin: i32,
+        nouts: Vec<i32>,
+        init: &ParamInit,
+        rng: &mut R,
+    ) -> Self {
         let mut layers = Vec::new();
         let mut prev_nout = nin;
         for nout in nouts {
-            layers.push(Layer::new(prev_nout, nout));
+            layers.push(Layer::with_init(prev_nout, nout, init, rng));
             prev_nout = nout;
         }
-        MLP(layers)
+        MLP(layers).output_activation(Activation::Linear)
     }
 
-    pub fn call(&self, inputs: &Vec<Value>) -> Vec<Value> {
-        let mut outputs = inputs.clone();
+    /// Sets the activation of every layer, one per layer.
+    pub fn activations(mut self, activations: Vec<Activation>) -> Self {
+        assert_eq!(
+            activations.len(),
+            self.0.len(),
+            "expected one activation per layer"
+        );
+        for (layer, activation) in self.0.iter_mut().zip(activations) {
+            layer.set_activation(activation);
+        }
+        self
+    }
+
+    /// Sets the activation of the last layer, e.g. `Sigmoid` or `Softmax`
+    /// for a classification head.
+    pub fn output_activation(mut self, activation: Activation) -> Self {
+        if let Some(layer) = self.0.last_mut() {
+            layer.set_activation(activation);
+        }
+        self
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let mut outputs = inputs.to_vec();
         for layer in self.0.iter() {
             outputs = layer.call(&outputs);
         }
         outputs
     }
 
+    /// Evaluates the model on plain floats, without building an autodiff
+    /// graph. Gives the same outputs as `call`, much faster; use it when no
+    /// gradients are needed.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let mut outputs = inputs.to_vec();
+        for layer in self.0.iter() {
+            outputs = layer.predict(&outputs);
+        }
+        outputs
+    }
+
     pub fn parameters(&self) -> Vec<Value> {
         self.0.iter().flat_map(|layer| layer.parameters()).collect()
     }
+
+    /// Freezes the layers whose indices fall in `range`, e.g. `..2` to
+    /// fine-tune all but the first two.
+    pub fn freeze_layers(&self, range: impl RangeBounds<usize>) {
+        for layer in self.layers_in(range) {
+            layer.freeze();
+        }
+    }
+
+    pub fn unfreeze_layers(&self, range: impl RangeBounds<usize>) {
+        for layer in self.layers_in(range) {
+            layer.unfreeze();
+        }
+    }
+
+    fn layers_in(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = &Layer> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| range.contains(i))
+            .map(|(_, layer)| layer)
+    }
+
+    /// `parameters()` with their paths, e.g. `layers.1.neurons.3.w.2` and
+    /// `layers.1.neurons.3.b`. Paths are stable for a given architecture.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(i, layer)| prefixed(&format!("layers.{}", i), layer.named_parameters()))
+            .collect()
+    }
+
+    /// The shape, activation, parameter count and weight statistics of
+    /// every layer. Prints as a table.
+    pub fn summary(&self) -> Summary {
+        let layers = self
+            .0
+            .iter()
+            .map(|layer| LayerSummary {
+                nin: layer.nin(),
+                nout: layer.nout(),
+                activation: layer.activation(),
+                params: layer.parameters().len(),
+                weights: WeightStats::of(&layer.weights().concat()),
+            })
+            .collect();
+
+        Summary {
+            layers,
+            total_params: self.parameters().len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub layers: Vec<LayerSummary>,
+    pub total_params: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSummary {
+    pub nin: usize,
+    pub nout: usize,
+    pub activation: Activation,
+    /// Weights and biases.
+    pub params: usize,
+    pub weights: WeightStats,
+}
+
+/// Statistics of the weights of a layer, biases excluded. All zero for a
+/// layer without weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeightStats {
+    pub mean: f64,
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl WeightStats {
+    pub fn of(weights: &[f64]) -> Self {
+        if weights.is_empty() {
+            return WeightStats {
+                mean: 0.0,
+                std: 0.0,
+                min: 0.0,
+                max: 0.0,
+            };
+        }
+
+        let n = weights.len() as f64;
+        let mean = weights.iter().sum::<f64>() / n;
+        WeightStats {
+            mean,
+            std: (weights.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / n).sqrt(),
+            min: weights.iter().copied().fold(f64::INFINITY, f64::min),
+            max: weights.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = format!(
+            "{:<7} {:>6} {:>6} {:<10} {:>8} {:>9} {:>9} {:>9} {:>9}",
+            "layer", "in", "out", "activation", "params", "mean", "std", "min", "max"
+        );
+        let rule = "-".repeat(header.len());
+
+        writeln!(f, "{}", header)?;
+        writeln!(f, "{}", rule)?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let w = layer.weights;
+            writeln!(
+                f,
+                "{:<7} {:>6} {:>6} {:<10} {:>8} {:>9.4} {:>9.4} {:>9.4} {:>9.4}",
+                i,
+                layer.nin,
+                layer.nout,
+                layer.activation.to_string(),
+                layer.params,
+                w.mean,
+                w.std,
+                w.min,
+                w.max
+            )?;
+        }
+        writeln!(f, "{}", rule)?;
+        write!(f, "total params: {}", self.total_params)
+    }
+}
+
+impl Module for Layer {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        Layer::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Layer::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        Layer::named_parameters(self)
+    }
+}
+
+impl Module for MLP {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        MLP::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        MLP::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        MLP::named_parameters(self)
+    }
+}
+
+/// The JSON form of an `MLP`: its architecture and every weight and bias,
+/// plus the preprocessing its inputs go through, if saved with one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MlpJson {
+    pub nin: usize,
+    pub nouts: Vec<usize>,
+    pub layers: Vec<LayerJson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<Pipeline>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerJson {
+    pub nin: usize,
+    pub nout: usize,
+    pub activation: Activation,
+    pub weights: Vec<Vec<f64>>,
+    pub biases: Vec<f64>,
+}
+
+impl MLP {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.json(None)).unwrap()
+    }
+
+    /// `to_json` with `pipeline` stored alongside the weights, so the model
+    /// and the transforms it was trained behind load together.
+    pub fn to_json_with_pipeline(&self, pipeline: &Pipeline) -> String {
+        serde_json::to_string_pretty(&self.json(Some(pipeline.clone()))).unwrap()
+    }
+
+    fn json(&self, pipeline: Option<Pipeline>) -> MlpJson {
+        let layers = self
+            .0
+            .iter()
+            .map(|layer| LayerJson {
+                nin: layer.nin(),
+                nout: layer.nout(),
+                activation: layer.activation(),
+                weights: layer.weights(),
+                biases: layer.biases(),
+            })
+            .collect::<Vec<LayerJson>>();
+
+        MlpJson {
+            nin: self.0.first().map_or(0, |layer| layer.nin()),
+            nouts: layers.iter().map(|layer| layer.nout).collect(),
+            layers,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds a model from `to_json` output, checking that every shape
+    /// agrees with the recorded architecture. A stored pipeline is ignored.
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        MLP::from_json_with_pipeline(json).map(|(mlp, _)| mlp)
+    }
+
+    /// `from_json` that also returns the pipeline stored by
+    /// `to_json_with_pipeline`, if any.
+    pub fn from_json_with_pipeline(json: &str) -> io::Result<(Self, Option<Pipeline>)> {
+        let json = serde_json::from_str::<MlpJson>(json).map_err(io::Error::from)?;
+        let invalid = |msg: String| io::Error::new(ErrorKind::InvalidData, msg);
+
+        if json.nouts.len() != json.layers.len() {
+            return Err(invalid(format!(
+                "nouts lists {} layers but {} are stored",
+                json.nouts.len(),
+                json.layers.len()
+            )));
+        }
+
+        let mut prev_nout = json.nin;
+        let mut layers = Vec::with_capacity(json.layers.len());
+        for (i, (layer, nout)) in json.layers.into_iter().zip(json.nouts).enumerate() {
+            if layer.nin != prev_nout || layer.nout != nout {
+                return Err(invalid(format!(
+                    "layer {} is {}x{}, expected {}x{}",
+                    i, layer.nin, layer.nout, prev_nout, nout
+                )));
+            }
+            if layer.weights.len() != nout
+                || layer.biases.len() != nout
+                || layer.weights.iter().any(|row| row.len() != layer.nin)
+            {
+                return Err(invalid(format!(
+                    "layer {}: weights or biases do not match {}x{}",
+                    i, layer.nin, layer.nout
+                )));
+            }
+
+            let neurons = layer
+                .weights
+                .into_iter()
+                .zip(layer.biases)
+                .map(|(w, b)| Neuron::from_weights(w, b, layer.activation))
+                .collect();
+            layers.push(Layer(neurons));
+            prev_nout = nout;
+        }
+
+        Ok((MLP(layers), json.pipeline))
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> io::Result<Self> {
+        MLP::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save_json_with_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        path: impl AsRef<Path>,
+    ) -> io::Result<()> {
+        fs::write(path, self.to_json_with_pipeline(pipeline))
+    }
+
+    pub fn load_json_with_pipeline(path: impl AsRef<Path>) -> io::Result<(Self, Option<Pipeline>)> {
+        MLP::from_json_with_pipeline(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+    use std::rc::Rc;
+
+    fn data(params: &[Value]) -> Vec<f64> {
+        params.iter().map(|p| p.borrow().data).collect()
+    }
+
+    #[test]
+    fn layers_draw_their_neurons_one_after_the_other() {
+        let mut rng = ChaCha12Rng::seed_from_u64(5);
+        let layer = Layer::with_rng(3, 4, &mut rng);
+
+        let mut rng = ChaCha12Rng::seed_from_u64(5);
+        let neurons = (0..4)
+            .flat_map(|_| Neuron::with_rng(3, true, &mut rng).parameters())
+            .collect::<Vec<Value>>();
+
+        assert_eq!(data(&layer.parameters()), data(&neurons));
+    }
+
+    #[test]
+    fn models_build_from_a_dyn_rng() {
+        let mut rng = ChaCha12Rng::seed_from_u64(5);
+        let rng: &mut dyn RngCore = &mut rng;
+        let model = MLP::with_rng(2, vec![3, 1], rng);
+        assert_eq!(model.parameters().len(), 13);
+    }
+
+    #[test]
+    fn json_round_trip_is_exact() {
+        let mut rng = ChaCha12Rng::seed_from_u64(9);
+        let model = MLP::with_rng(3, vec![4, 5, 2], &mut rng).activations(vec![
+            Activation::ReLU,
+            Activation::Sigmoid,
+            Activation::Softmax,
+        ]);
+
+        let loaded = MLP::from_json(&model.to_json()).unwrap();
+
+        assert_eq!(loaded.to_json(), model.to_json());
+        assert_eq!(
+            data(&loaded.parameters())
+                .iter()
+                .map(|x| x.to_bits())
+                .collect::<Vec<u64>>(),
+            data(&model.parameters())
+                .iter()
+                .map(|x| x.to_bits())
+                .collect::<Vec<u64>>()
+        );
+        for (a, b) in loaded.0.iter().zip(&model.0) {
+            assert_eq!(a.activation(), b.activation());
+        }
+
+        let x = [0.1, -2.0, 7.5];
+        assert_eq!(loaded.predict(&x), model.predict(&x));
+    }
+
+    #[test]
+    fn json_with_a_wrong_shape_is_rejected() {
+        let mut rng = ChaCha12Rng::seed_from_u64(9);
+        let json = MLP::with_rng(2, vec![3, 1], &mut rng).to_json().replacen(
+            "\"nin\": 2",
+            "\"nin\": 4",
+            1,
+        );
+
+        let err = MLP::from_json(&json).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn summary_reports_shapes_counts_and_weight_stats() {
+        let mut rng = ChaCha12Rng::seed_from_u64(4);
+        let model = MLP::with_rng(3, vec![4, 2], &mut rng)
+            .activations(vec![Activation::ReLU, Activation::Softmax]);
+        // the last layer's weights become 1..=8 and its biases stay as drawn
+        for (w, value) in model.0[1].0.iter().flat_map(|n| &n.0).zip(1..) {
+            w.0.borrow_mut().data = value as f64;
+        }
+
+        let summary = model.summary();
+        let shapes = summary
+            .layers
+            .iter()
+            .map(|l| (l.nin, l.nout, l.activation, l.params))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shapes,
+            [
+                (3, 4, Activation::ReLU, 16),
+                (4, 2, Activation::Softmax, 10)
+            ]
+        );
+        assert_eq!(summary.total_params, 26);
+
+        let drawn = model.0[0].weights().concat();
+        assert_eq!(summary.layers[0].weights, WeightStats::of(&drawn));
+        let stats = summary.layers[0].weights;
+        assert!(-1.0 <= stats.min && stats.min <= stats.mean && stats.mean <= stats.max);
+        assert!(stats.max < 1.0 && stats.std > 0.0);
+
+        assert_eq!(
+            summary.layers[1].weights,
+            WeightStats {
+                mean: 4.5,
+                std: 5.25f64.sqrt(),
+                min: 1.0,
+                max: 8.0,
+            }
+        );
+
+        let table = summary.to_string();
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.ends_with("total params: 26"));
+    }
+
+    #[test]
+    fn named_parameters_are_hierarchical_unique_and_in_parameters_order() {
+        let mut rng = ChaCha12Rng::seed_from_u64(6);
+        let model = MLP::with_rng(3, vec![2, 1], &mut rng);
+
+        let named = model.named_parameters();
+        let names = named
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            names,
+            [
+                "layers.0.neurons.0.w.0",
+                "layers.0.neurons.0.w.1",
+                "layers.0.neurons.0.w.2",
+                "layers.0.neurons.0.b",
+                "layers.0.neurons.1.w.0",
+                "layers.0.neurons.1.w.1",
+                "layers.0.neurons.1.w.2",
+                "layers.0.neurons.1.b",
+                "layers.1.neurons.0.w.0",
+                "layers.1.neurons.0.w.1",
+                "layers.1.neurons.0.b",
+            ]
+        );
+
+        let parameters = model.parameters();
+        assert_eq!(named.len(), parameters.len());
+        for ((_, named), param) in named.iter().zip(&parameters) {
+            assert!(Rc::ptr_eq(&named.0, &param.0));
+        }
+    }
+
+    #[test]
+    fn predict_matches_call_for_every_activation() {
+        let mut rng = ChaCha12Rng::seed_from_u64(8);
+        let xs = [[0.3, -1.2, 2.5], [0.0, 0.0, 0.0], [-4.0, 1.0, 0.5]];
+
+        for activation in [
+            Activation::Tanh,
+            Activation::ReLU,
+            Activation::Sigmoid,
+            Activation::Linear,
+            Activation::Softmax,
+        ] {
+            let model =
+                MLP::with_rng(3, vec![4, 3], &mut rng).activations(vec![activation, activation]);
+            for x in xs {
+                let inputs = x
+                    .iter()
+                    .map(|x| Value::constant(*x, "x"))
+                    .collect::<Vec<Value>>();
+                let called = data(&model.call(&inputs));
+                let predicted = model.predict(&x);
+
+                assert_eq!(predicted.len(), called.len());
+                for (p, c) in predicted.iter().zip(&called) {
+                    assert!((p - c).abs() < 1e-12, "{:?}: {} != {}", activation, p, c);
+                }
+                assert_eq!(Module::predict(&model, &x), predicted);
+                assert_eq!(Module::predict(&model.0[0], &x), model.0[0].predict(&x));
+            }
+        }
+    }
+
+    #[test]
+    fn frozen_layers_get_no_gradient() {
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let model = MLP::with_rng(2, vec![4, 4, 1], &mut rng);
+        model.freeze_layers(0..1);
+
+        let x = [Value::constant(0.5, "x"), Value::constant(-1.0, "x")];
+        model.call(&x)[0].clone().backward();
+
+        assert!(model.0[0]
+            .parameters()
+            .iter()
+            .all(|p| p.borrow().grad == 0.0));
+        assert!(model.0[2]
+            .parameters()
+            .iter()
+            .any(|p| p.borrow().grad != 0.0));
+    }
 }
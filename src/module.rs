@@ -0,0 +1,306 @@
+use crate::engine::*;
+
+/// Anything that maps a row of values to a row of values and may own
+/// trainable parameters: `Neuron`, `Layer`, `MLP`, `Dropout`, the
+/// normalization layers, the skip connections, the convolution and pooling
+/// layers and `Sequential` itself.
+pub trait Module {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value>;
+
+    /// `forward` on every sample of a batch. Modules that look across the
+    /// batch, like `BatchNorm1d`, override it.
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        batch.iter().map(|x| self.forward(x)).collect()
+    }
+
+    /// `forward` on plain floats, in eval mode. The default builds the same
+    /// graph as `forward` and throws it away, so it costs as much as a
+    /// forward pass while training; every module in this crate overrides it
+    /// with a graph-free version, and new modules should too.
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let inputs = inputs
+            .iter()
+            .map(|x| Value::constant(*x, "x"))
+            .collect::<Vec<Value>>();
+        self.forward(&inputs)
+            .iter()
+            .map(|v| v.borrow().data)
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    /// `parameters()` with their paths. Defaults to their positions.
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.parameters()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (i.to_string(), p))
+            .collect()
+    }
+
+    /// State that isn't trained but affects the output, like the running
+    /// statistics of `BatchNorm1d`, flattened. Checkpoints save it next to
+    /// the parameters.
+    fn buffers(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    /// Restores what `buffers` returned.
+    fn load_buffers(&self, _buffers: &[f64]) {}
+
+    /// Switches to training mode, for modules that behave differently while
+    /// training.
+    fn train(&self) {}
+
+    fn eval(&self) {}
+
+    fn zero_grad(&self) {
+        for p in self.parameters() {
+            p.0.borrow_mut().grad = 0.0;
+        }
+    }
+}
+
+/// Modules applied one after the other, such as `Layer`s with `Dropout` or
+/// normalization in between. Batches go through `forward_batch` of every
+/// module, so `BatchNorm1d` sees the whole batch.
+///
+/// Parameters are named by the position of their module, as `{i}.{name}`.
+#[derive(Default)]
+pub struct Sequential(pub Vec<Box<dyn Module>>);
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential::default()
+    }
+
+    pub fn then(mut self, module: impl Module + 'static) -> Self {
+        self.0.push(Box::new(module));
+        self
+    }
+}
+
+impl Module for Sequential {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.0
+            .iter()
+            .fold(inputs.to_vec(), |x, module| module.forward(&x))
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        self.0
+            .iter()
+            .fold(batch.to_vec(), |x, module| module.forward_batch(&x))
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        self.0
+            .iter()
+            .fold(inputs.to_vec(), |x, module| module.predict(&x))
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.0
+            .iter()
+            .flat_map(|module| module.parameters())
+            .collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(i, module)| {
+                module
+                    .named_parameters()
+                    .into_iter()
+                    .map(move |(name, p)| (format!("{}.{}", i, name), p))
+            })
+            .collect()
+    }
+
+    fn buffers(&self) -> Vec<f64> {
+        self.0.iter().flat_map(|module| module.buffers()).collect()
+    }
+
+    fn load_buffers(&self, buffers: &[f64]) {
+        load_buffers_in_order(self.0.iter().map(|module| &**module), buffers);
+    }
+
+    fn train(&self) {
+        self.0.iter().for_each(|module| module.train());
+    }
+
+    fn eval(&self) {
+        self.0.iter().for_each(|module| module.eval());
+    }
+}
+
+/// Splits `buffers` among `modules` by the length of their own buffers.
+fn load_buffers_in_order<'a>(modules: impl Iterator<Item = &'a dyn Module>, buffers: &[f64]) {
+    let mut rest = buffers;
+    for module in modules {
+        let (head, tail) = rest.split_at(module.buffers().len());
+        module.load_buffers(head);
+        rest = tail;
+    }
+    assert!(rest.is_empty(), "{} buffers left over", rest.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+    use crate::{
+        activation::Activation,
+        dropout::Dropout,
+        mlp::{Layer, MLP},
+        norm::{BatchNorm1d, LayerNorm},
+    };
+
+    /// Records the last mode it was switched to.
+    struct Probe(Rc<Cell<Option<bool>>>);
+
+    impl Module for Probe {
+        fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+            inputs.to_vec()
+        }
+
+        fn train(&self) {
+            self.0.set(Some(true));
+        }
+
+        fn eval(&self) {
+            self.0.set(Some(false));
+        }
+    }
+
+    fn data(values: &[Value]) -> Vec<f64> {
+        values.iter().map(|v| v.borrow().data).collect()
+    }
+
+    #[test]
+    fn forward_applies_the_modules_in_order() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let model = Sequential::new()
+            .then(Layer::with_rng(2, 3, &mut rng))
+            .then(Layer::with_rng(3, 1, &mut rng));
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let mlp = MLP::with_rng(2, vec![3, 1], &mut rng)
+            .activations(vec![Activation::Tanh, Activation::Tanh]);
+
+        let batch = vec![
+            vec![Value::new(0.5, "x"), Value::new(-1.0, "x")],
+            vec![Value::new(2.0, "x"), Value::new(0.25, "x")],
+        ];
+        for x in &batch {
+            assert_eq!(data(&model.forward(x)), data(&mlp.call(x)));
+            assert_eq!(model.predict(&data(x)), mlp.predict(&data(x)));
+        }
+        let outputs = model.forward_batch(&batch);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(data(&outputs[1]), data(&mlp.call(&batch[1])));
+
+        assert_eq!(data(&Sequential::new().forward(&batch[0])), data(&batch[0]));
+    }
+
+    #[test]
+    fn parameters_follow_the_modules_in_order() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let model = Sequential::new()
+            .then(Layer::with_rng(2, 2, &mut rng))
+            .then(Dropout::new(0.5))
+            .then(LayerNorm::new(2))
+            .then(Layer::with_rng(2, 1, &mut rng));
+
+        let expected = model
+            .0
+            .iter()
+            .flat_map(|module| module.parameters())
+            .collect::<Vec<Value>>();
+        let parameters = model.parameters();
+        assert_eq!(parameters.len(), 6 + 4 + 3);
+        assert!(parameters
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| Rc::ptr_eq(&a.0, &b.0)));
+
+        let named = model.named_parameters();
+        let names = named.iter().map(|(name, _)| name.as_str());
+        assert!(names.clone().eq([
+            "0.neurons.0.w.0",
+            "0.neurons.0.w.1",
+            "0.neurons.0.b",
+            "0.neurons.1.w.0",
+            "0.neurons.1.w.1",
+            "0.neurons.1.b",
+            "2.gain.0",
+            "2.gain.1",
+            "2.bias.0",
+            "2.bias.1",
+            "3.neurons.0.w.0",
+            "3.neurons.0.w.1",
+            "3.neurons.0.b",
+        ]));
+        assert!(named
+            .iter()
+            .zip(&parameters)
+            .all(|((_, a), b)| Rc::ptr_eq(&a.0, &b.0)));
+    }
+
+    #[test]
+    fn train_and_eval_reach_nested_modules() {
+        let (outer, inner) = (Rc::new(Cell::new(None)), Rc::new(Cell::new(None)));
+        let model = Sequential::new().then(Probe(outer.clone())).then(
+            Sequential::new()
+                .then(Dropout::new(0.5))
+                .then(Probe(inner.clone())),
+        );
+
+        model.eval();
+        assert_eq!((outer.get(), inner.get()), (Some(false), Some(false)));
+        // the nested dropout is the identity in eval mode
+        let x = (0..20)
+            .map(|i| Value::new(i as f64, "x"))
+            .collect::<Vec<Value>>();
+        assert_eq!(data(&model.forward(&x)), data(&x));
+
+        model.train();
+        assert_eq!((outer.get(), inner.get()), (Some(true), Some(true)));
+        assert_ne!(data(&model.forward(&x)), data(&x));
+    }
+
+    #[test]
+    fn buffers_are_split_in_module_order() {
+        let model = Sequential::new()
+            .then(BatchNorm1d::new(2))
+            .then(Layer::new(2, 3))
+            .then(BatchNorm1d::new(3));
+        assert_eq!(
+            model.buffers(),
+            [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0]
+        );
+
+        let buffers = (1..=10).map(f64::from).collect::<Vec<f64>>();
+        model.load_buffers(&buffers);
+        assert_eq!(model.0[0].buffers(), [1.0, 2.0, 3.0, 4.0]);
+        assert!(model.0[1].buffers().is_empty());
+        assert_eq!(model.0[2].buffers(), [5.0, 6.0, 7.0, 8.0, 9.0, 10.0]);
+        assert_eq!(model.buffers(), buffers);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 buffers left over")]
+    fn extra_buffers_are_rejected() {
+        Sequential::new()
+            .then(BatchNorm1d::new(1))
+            .load_buffers(&[0.0, 1.0, 2.0, 3.0]);
+    }
+}
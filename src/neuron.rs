@@ -1,31 +1,52 @@
-use crate::engine::*;
-use rand::{
-    self,
-    distributions::{Distribution, Uniform},
-};
+use crate::{activation::Activation, engine::*, init::ParamInit, module::Module, random};
+use rand::Rng;
 
 #[derive(Debug)]
-pub struct Neuron(pub Vec<Value>, pub Value, pub bool);
+pub struct Neuron(pub Vec<Value>, pub Value, pub Activation);
 
 impl Neuron {
-    pub fn new(nin: i32, nonlin: bool) -> Self {
-        let mut weights = Vec::new();
-        let between = Uniform::from(-1.0..1.0);
-        let mut rng = rand::thread_rng();
-        for _ in 0..nin {
-            weights.push(Value::new(between.sample(&mut rng), "weight"));
-        }
-        // let weight = Value::new(rand::random::<f64>(), "weight");
-        let bias = Value::new(between.sample(&mut rng), "bias");
+    /// `activation` is an `Activation`, or a `bool` for tanh or linear as
+    /// in micrograd.
+    pub fn new(nin: i32, activation: impl Into<Activation>) -> Self {
+        random::with_rng(|rng| Neuron::with_rng(nin, activation, rng))
+    }
+
+    pub fn with_rng<R: Rng + ?Sized>(
+        nin: i32,
+        activation: impl Into<Activation>,
+        rng: &mut R,
+    ) -> Self {
+        Neuron::with_init(nin, activation, &ParamInit::default(), rng)
+    }
+
+    pub fn with_init<R: Rng + ?Sized>(
+        nin: i32,
+        activation: impl Into<Activation>,
+        init: &ParamInit,
+        rng: &mut R,
+    ) -> Self {
+        let nin = nin as usize;
+        let weights = init.weight.matrix(1, nin, nin, 1, rng).remove(0);
+        let bias = init.bias.matrix(1, 1, nin, 1, rng)[0][0];
+
+        Neuron::from_weights(weights, bias, activation)
+    }
+
+    pub fn from_weights(weights: Vec<f64>, bias: f64, activation: impl Into<Activation>) -> Self {
+        let weights = weights
+            .into_iter()
+            .map(|w| Value::new(w, "weight"))
+            .collect();
+        let bias = Value::new(bias, "bias");
 
-        Neuron(weights, bias, nonlin)
+        Neuron(weights, bias, activation.into())
     }
 
     pub fn from(nin: i32) -> Neuron {
         Neuron::new(nin, true)
     }
 
-    pub fn call(&self, inputs: &Vec<Value>) -> Value {
+    pub fn call(&self, inputs: &[Value]) -> Value {
         let bias = self.1.clone();
 
         let sum = self
@@ -35,13 +56,21 @@ impl Neuron {
             .map(|(wi, xi)| xi.to_owned() * wi.to_owned())
             .sum::<Value>();
 
-        let out = sum + bias;
+        self.2.call(sum + bias)
+    }
 
-        if self.2 {
-            out._tanh("output")
-        } else {
-            out
-        }
+    /// `call` on plain floats, without building a graph. Sums in the same
+    /// order as `call`, so the result is identical.
+    pub fn predict(&self, inputs: &[f64]) -> f64 {
+        let sum = self
+            .0
+            .iter()
+            .zip(inputs)
+            .map(|(wi, xi)| xi * wi.borrow().data)
+            .reduce(|acc, x| acc + x)
+            .unwrap_or(0.0);
+
+        self.2.apply(sum + self.1.borrow().data)
     }
 
     pub fn parameters(&self) -> Vec<Value> {
@@ -49,4 +78,35 @@ impl Neuron {
         out.push(self.1.clone());
         out
     }
+
+    /// `parameters()` with their paths: `w.{i}` for the weight on input `i`
+    /// and `b` for the bias.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        let mut out = self
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (format!("w.{}", i), w.clone()))
+            .collect::<Vec<(String, Value)>>();
+        out.push(("b".to_string(), self.1.clone()));
+        out
+    }
+}
+
+impl Module for Neuron {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        vec![self.call(inputs)]
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        vec![Neuron::predict(self, inputs)]
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Neuron::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        Neuron::named_parameters(self)
+    }
 }
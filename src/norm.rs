@@ -0,0 +1,364 @@
+use std::cell::{Cell, RefCell};
+
+use crate::{engine::*, module::Module};
+
+/// Normalizes each sample over its features, then applies a learnable
+/// per-feature gain and bias. Behaves the same in training and eval.
+pub struct LayerNorm {
+    pub gain: Vec<Value>,
+    pub bias: Vec<Value>,
+    pub eps: f64,
+}
+
+impl LayerNorm {
+    pub fn new(features: usize) -> Self {
+        LayerNorm {
+            gain: (0..features).map(|_| Value::new(1.0, "gain")).collect(),
+            bias: (0..features).map(|_| Value::new(0.0, "bias")).collect(),
+            eps: 1e-5,
+        }
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        assert_eq!(inputs.len(), self.gain.len(), "input width differs");
+
+        let (mean, inv_std) = moments(inputs, self.eps);
+        inputs
+            .iter()
+            .zip(self.gain.iter().zip(&self.bias))
+            .map(|(x, (gain, bias))| {
+                (x.clone() - mean.clone()) * inv_std.clone() * gain.clone() + bias.clone()
+            })
+            .collect()
+    }
+
+    pub fn call_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        batch.iter().map(|x| self.call(x)).collect()
+    }
+
+    /// `call` on plain floats, without building a graph.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let n = inputs.len() as f64;
+        let mean = inputs.iter().sum::<f64>() / n;
+        let var = inputs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        let inv_std = 1.0 / (var + self.eps).sqrt();
+
+        inputs
+            .iter()
+            .zip(self.gain.iter().zip(&self.bias))
+            .map(|(x, (gain, bias))| (x - mean) * inv_std * gain.borrow().data + bias.borrow().data)
+            .collect()
+    }
+
+    /// The gains, then the biases.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.gain.iter().chain(&self.bias).cloned().collect()
+    }
+
+    /// `parameters()` with their paths, `gain.{i}` and `bias.{i}`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        named_affine(&self.gain, &self.bias)
+    }
+}
+
+/// Normalizes each feature over a batch, then applies a learnable
+/// per-feature gain and bias.
+///
+/// In training mode `call_batch` uses the statistics of the batch and
+/// updates exponential running averages of them; in eval mode, and for
+/// single samples through `call`, the running averages are used instead. A
+/// new batch norm starts in training mode.
+pub struct BatchNorm1d {
+    pub gain: Vec<Value>,
+    pub bias: Vec<Value>,
+    pub eps: f64,
+    /// Weight of the newest batch in the running averages.
+    pub momentum: f64,
+    pub running_mean: RefCell<Vec<f64>>,
+    /// Unbiased estimate, as in PyTorch.
+    pub running_var: RefCell<Vec<f64>>,
+    training: Cell<bool>,
+}
+
+impl BatchNorm1d {
+    pub fn new(features: usize) -> Self {
+        BatchNorm1d {
+            gain: (0..features).map(|_| Value::new(1.0, "gain")).collect(),
+            bias: (0..features).map(|_| Value::new(0.0, "bias")).collect(),
+            eps: 1e-5,
+            momentum: 0.1,
+            running_mean: RefCell::new(vec![0.0; features]),
+            running_var: RefCell::new(vec![1.0; features]),
+            training: Cell::new(true),
+        }
+    }
+
+    pub fn call_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        if !self.is_training() {
+            return batch.iter().map(|x| self.call(x)).collect();
+        }
+        assert!(!batch.is_empty(), "batch norm of an empty batch");
+
+        let n = batch.len() as f64;
+        let mut columns = Vec::with_capacity(self.gain.len());
+        for (j, (gain, bias)) in self.gain.iter().zip(&self.bias).enumerate() {
+            let column = batch.iter().map(|x| x[j].clone()).collect::<Vec<Value>>();
+            let (mean, inv_std) = moments(&column, self.eps);
+
+            let batch_mean = mean.borrow().data;
+            let batch_var = column
+                .iter()
+                .map(|x| (x.borrow().data - batch_mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.0).max(1.0);
+            let m = self.momentum;
+            let mut running_mean = self.running_mean.borrow_mut();
+            let mut running_var = self.running_var.borrow_mut();
+            running_mean[j] = (1.0 - m) * running_mean[j] + m * batch_mean;
+            running_var[j] = (1.0 - m) * running_var[j] + m * batch_var;
+
+            columns.push(
+                column
+                    .into_iter()
+                    .map(|x| (x - mean.clone()) * inv_std.clone() * gain.clone() + bias.clone())
+                    .collect::<Vec<Value>>(),
+            );
+        }
+
+        (0..batch.len())
+            .map(|i| columns.iter().map(|column| column[i].clone()).collect())
+            .collect()
+    }
+
+    /// Normalizes one sample with the running averages.
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        assert_eq!(inputs.len(), self.gain.len(), "input width differs");
+
+        let running_mean = self.running_mean.borrow();
+        let running_var = self.running_var.borrow();
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(j, x)| {
+                let scale = 1.0 / (running_var[j] + self.eps).sqrt();
+                (x.clone() - running_mean[j]) * scale * self.gain[j].clone() + self.bias[j].clone()
+            })
+            .collect()
+    }
+
+    /// `call` on plain floats, without building a graph.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let running_mean = self.running_mean.borrow();
+        let running_var = self.running_var.borrow();
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(j, x)| {
+                let scale = 1.0 / (running_var[j] + self.eps).sqrt();
+                (x - running_mean[j]) * scale * self.gain[j].borrow().data
+                    + self.bias[j].borrow().data
+            })
+            .collect()
+    }
+
+    pub fn train(&self) {
+        self.training.set(true);
+    }
+
+    pub fn eval(&self) {
+        self.training.set(false);
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training.get()
+    }
+
+    /// The gains, then the biases. The running averages are not trained.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.gain.iter().chain(&self.bias).cloned().collect()
+    }
+
+    /// `parameters()` with their paths, `gain.{i}` and `bias.{i}`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        named_affine(&self.gain, &self.bias)
+    }
+}
+
+impl Module for LayerNorm {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        LayerNorm::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        LayerNorm::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        LayerNorm::named_parameters(self)
+    }
+}
+
+impl Module for BatchNorm1d {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        self.call_batch(batch)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        BatchNorm1d::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        BatchNorm1d::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        BatchNorm1d::named_parameters(self)
+    }
+
+    /// The running means, then the running variances.
+    fn buffers(&self) -> Vec<f64> {
+        let running_mean = self.running_mean.borrow();
+        let running_var = self.running_var.borrow();
+        running_mean
+            .iter()
+            .chain(running_var.iter())
+            .copied()
+            .collect()
+    }
+
+    fn load_buffers(&self, buffers: &[f64]) {
+        let (mean, var) = buffers.split_at(buffers.len() / 2);
+        self.running_mean.replace(mean.to_vec());
+        self.running_var.replace(var.to_vec());
+    }
+
+    fn train(&self) {
+        BatchNorm1d::train(self)
+    }
+
+    fn eval(&self) {
+        BatchNorm1d::eval(self)
+    }
+}
+
+fn named_affine(gain: &[Value], bias: &[Value]) -> Vec<(String, Value)> {
+    let gain = gain
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (format!("gain.{}", i), g.clone()));
+    let bias = bias
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (format!("bias.{}", i), b.clone()));
+    gain.chain(bias).collect()
+}
+
+/// The mean of `values` and `1 / sqrt(var + eps)` with the biased variance,
+/// as graph nodes so gradients flow through both.
+fn moments(values: &[Value], eps: f64) -> (Value, Value) {
+    let inv_n = 1.0 / values.len() as f64;
+
+    let mean = values.iter().cloned().sum::<Value>() * inv_n;
+    let var = values
+        .iter()
+        .map(|x| {
+            let centered = x.clone() - mean.clone();
+            centered.clone() * centered
+        })
+        .sum::<Value>()
+        * inv_n;
+    let inv_std = (var + eps)._pow(&Value::constant(-0.5, ""), "inv_std");
+
+    (mean, inv_std)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::assert_gradients;
+
+    fn leaves(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|x| Value::new(*x, "x")).collect()
+    }
+
+    /// A weighted sum of `rows`, so every output gets a different upstream
+    /// gradient.
+    fn weighted(rows: Vec<Vec<Value>>) -> Value {
+        rows.into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, v)| v * (i as f64 * 0.7 - 1.0))
+            .sum()
+    }
+
+    /// Sets the gains and biases away from 1 and 0, so their gradients are
+    /// checked in general position.
+    fn perturb(gain: &[Value], bias: &[Value]) {
+        for (i, (g, b)) in gain.iter().zip(bias).enumerate() {
+            g.0.borrow_mut().data = 0.5 + 0.3 * i as f64;
+            b.0.borrow_mut().data = 0.2 - 0.1 * i as f64;
+        }
+    }
+
+    #[test]
+    fn layer_norm_matches_finite_differences() {
+        let norm = LayerNorm::new(4);
+        perturb(&norm.gain, &norm.bias);
+        let x = leaves(&[0.3, -1.2, 2.5, 0.9]);
+
+        let mut inputs = norm.parameters();
+        inputs.extend(x.iter().cloned());
+        assert_gradients(&inputs, || weighted(vec![norm.call(&x)]));
+
+        let data = x.iter().map(|x| x.borrow().data).collect::<Vec<f64>>();
+        let outputs = norm.call(&x);
+        for (y, p) in outputs.iter().zip(norm.predict(&data)) {
+            assert!((y.borrow().data - p).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn batch_norm_matches_finite_differences_in_training() {
+        let norm = BatchNorm1d::new(3);
+        perturb(&norm.gain, &norm.bias);
+        let batch = [
+            leaves(&[0.3, -1.2, 2.5]),
+            leaves(&[1.1, 0.4, -0.7]),
+            leaves(&[-0.6, 2.0, 0.1]),
+            leaves(&[0.8, -0.3, 1.4]),
+        ];
+
+        let mut inputs = norm.parameters();
+        inputs.extend(batch.iter().flatten().cloned());
+        assert_gradients(&inputs, || weighted(norm.call_batch(&batch)));
+    }
+
+    #[test]
+    fn batch_norm_matches_finite_differences_in_eval() {
+        let norm = BatchNorm1d::new(3);
+        perturb(&norm.gain, &norm.bias);
+        norm.load_buffers(&[0.5, -0.2, 1.0, 2.0, 0.5, 1.5]);
+        norm.eval();
+        let x = leaves(&[0.3, -1.2, 2.5]);
+
+        let mut inputs = norm.parameters();
+        inputs.extend(x.iter().cloned());
+        assert_gradients(&inputs, || weighted(vec![norm.call(&x)]));
+        assert_gradients(&inputs, || {
+            weighted(norm.call_batch(std::slice::from_ref(&x)))
+        });
+
+        let outputs = norm.call(&x);
+        for (y, p) in outputs.iter().zip(norm.predict(&[0.3, -1.2, 2.5])) {
+            assert!((y.borrow().data - p).abs() < 1e-12);
+        }
+    }
+}
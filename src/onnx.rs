@@ -0,0 +1,662 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{activation::Activation, mlp::MLP};
+
+// A minimal ONNX writer and reader. The protobuf messages are encoded by hand
+// with the field numbers of onnx.proto, so exporting needs neither protoc nor
+// any runtime service. The reader only understands what the writer emits plus
+// MatMul and Add, which is enough to check an export against `MLP::call`;
+// see `OnnxModel` for the ops.
+
+const IR_VERSION: u64 = 8;
+const OPSET_VERSION: u64 = 13;
+
+// TensorProto.DataType
+const DOUBLE: u64 = 11;
+// AttributeProto.AttributeType
+const INT: u64 = 2;
+
+impl MLP {
+    /// Encodes the model as an ONNX `ModelProto`. Each layer becomes a Gemm
+    /// node (with `transB = 1`, as weights are stored one row per neuron)
+    /// followed by its activation. The graph maps `input` of shape
+    /// `[batch, nin]` to `output` of shape `[batch, nout]`, in doubles.
+    pub fn to_onnx(&self) -> Vec<u8> {
+        let nin = self.0.first().map_or(0, |layer| layer.nin());
+        let nout = self.0.last().map_or(nin, |layer| layer.nout());
+
+        let mut graph = Message::default();
+        let mut x = "input".to_string();
+        for (i, layer) in self.0.iter().enumerate() {
+            let weight = format!("layers.{}.weight", i);
+            let bias = format!("layers.{}.bias", i);
+            let weights = layer.weights().concat();
+            graph.message(5, &tensor(&weight, &[layer.nout(), layer.nin()], &weights));
+            graph.message(5, &tensor(&bias, &[layer.nout()], &layer.biases()));
+
+            let last = i + 1 == self.0.len();
+            let activation = match layer.activation() {
+                Activation::Tanh => Some("Tanh"),
+                Activation::ReLU => Some("Relu"),
+                Activation::Sigmoid => Some("Sigmoid"),
+                // Over the last axis by default since opset 13.
+                Activation::Softmax => Some("Softmax"),
+                Activation::Linear => None,
+            };
+            let gemm_out = match (last, activation) {
+                (true, None) => "output".to_string(),
+                _ => format!("layers.{}.gemm", i),
+            };
+
+            let mut gemm = node(
+                "Gemm",
+                &format!("layers.{}", i),
+                &[&x, &weight, &bias],
+                &gemm_out,
+            );
+            gemm.message(5, &int_attribute("transB", 1));
+            graph.message(1, &gemm);
+            x = gemm_out;
+
+            if let Some(op) = activation {
+                let out = if last {
+                    "output".to_string()
+                } else {
+                    format!("layers.{}.out", i)
+                };
+                graph.message(1, &node(op, &format!("layers.{}.act", i), &[&x], &out));
+                x = out;
+            }
+        }
+
+        if self.0.is_empty() {
+            graph.message(1, &node("Identity", "identity", &["input"], "output"));
+        }
+
+        graph.string(2, "mlp");
+        graph.message(11, &value_info("input", nin));
+        graph.message(12, &value_info("output", nout));
+
+        let mut opset = Message::default();
+        opset.string(1, "");
+        opset.varint(2, OPSET_VERSION);
+
+        let mut model = Message::default();
+        model.varint(1, IR_VERSION);
+        model.string(2, "rusty-micrograd");
+        model.message(7, &graph);
+        model.message(8, &opset);
+        model.0
+    }
+
+    pub fn save_onnx(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_onnx())
+    }
+}
+
+/// An encoded protobuf message under construction.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn key(&mut self, field: u64, wire_type: u64) {
+        write_varint(&mut self.0, field << 3 | wire_type);
+    }
+
+    fn varint(&mut self, field: u64, value: u64) {
+        self.key(field, 0);
+        write_varint(&mut self.0, value);
+    }
+
+    fn bytes(&mut self, field: u64, bytes: &[u8]) {
+        self.key(field, 2);
+        write_varint(&mut self.0, bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, field: u64, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u64, message: &Message) {
+        self.bytes(field, &message.0);
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn tensor(name: &str, dims: &[usize], data: &[f64]) -> Message {
+    let mut tensor = Message::default();
+    for dim in dims {
+        tensor.varint(1, *dim as u64);
+    }
+    tensor.varint(2, DOUBLE);
+    tensor.string(8, name);
+    let raw = data
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect::<Vec<u8>>();
+    tensor.bytes(9, &raw);
+    tensor
+}
+
+fn node(op_type: &str, name: &str, inputs: &[&str], output: &str) -> Message {
+    let mut node = Message::default();
+    for input in inputs {
+        node.string(1, input);
+    }
+    node.string(2, output);
+    node.string(3, name);
+    node.string(4, op_type);
+    node
+}
+
+fn int_attribute(name: &str, value: i64) -> Message {
+    let mut attribute = Message::default();
+    attribute.string(1, name);
+    attribute.varint(3, value as u64);
+    attribute.varint(20, INT);
+    attribute
+}
+
+/// A `[batch, width]` tensor of doubles, with a symbolic batch dimension.
+fn value_info(name: &str, width: usize) -> Message {
+    let mut batch = Message::default();
+    batch.string(2, "batch");
+    let mut features = Message::default();
+    features.varint(1, width as u64);
+
+    let mut shape = Message::default();
+    shape.message(1, &batch);
+    shape.message(1, &features);
+
+    let mut tensor_type = Message::default();
+    tensor_type.varint(1, DOUBLE);
+    tensor_type.message(2, &shape);
+
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+
+    let mut info = Message::default();
+    info.string(1, name);
+    info.message(2, &type_proto);
+    info
+}
+
+/// A dense tensor of doubles in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    pub dims: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub op_type: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: HashMap<String, i64>,
+}
+
+/// The graph of an ONNX model, decoded far enough to evaluate the networks
+/// `MLP::to_onnx` writes. Supports Gemm, MatMul, Add, Tanh, Relu, Sigmoid,
+/// Softmax (over the last axis) and Identity on double tensors of rank at
+/// most 2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnnxModel {
+    pub nodes: Vec<Node>,
+    pub initializers: HashMap<String, Tensor>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl OnnxModel {
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut model = OnnxModel {
+            nodes: Vec::new(),
+            initializers: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        let mut graph = None;
+        for field in fields(bytes) {
+            if let (7, Field::Bytes(bytes)) = field? {
+                graph = Some(bytes);
+            }
+        }
+        let graph = graph.ok_or_else(|| invalid("the model has no graph".to_string()))?;
+
+        for field in fields(graph) {
+            match field? {
+                (1, Field::Bytes(bytes)) => model.nodes.push(read_node(bytes)?),
+                (5, Field::Bytes(bytes)) => {
+                    let (name, tensor) = read_tensor(bytes)?;
+                    model.initializers.insert(name, tensor);
+                }
+                (11, Field::Bytes(bytes)) => model.inputs.push(read_name(bytes)?),
+                (12, Field::Bytes(bytes)) => model.outputs.push(read_name(bytes)?),
+                _ => {}
+            }
+        }
+
+        // graph inputs may also list the initializers
+        model
+            .inputs
+            .retain(|name| !model.initializers.contains_key(name));
+        Ok(model)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        OnnxModel::from_bytes(&fs::read(path)?)
+    }
+
+    /// Evaluates the graph on one row fed to its single input and returns
+    /// its first output, flattened.
+    pub fn run(&self, input: &[f64]) -> io::Result<Vec<f64>> {
+        let input_name = match self.inputs.as_slice() {
+            [name] => name,
+            _ => return Err(invalid("expected exactly one graph input".to_string())),
+        };
+
+        let mut values = self.initializers.clone();
+        values.insert(
+            input_name.clone(),
+            Tensor {
+                dims: vec![1, input.len()],
+                data: input.to_vec(),
+            },
+        );
+
+        for node in self.nodes.iter() {
+            let args = node
+                .inputs
+                .iter()
+                .map(|name| {
+                    values
+                        .get(name)
+                        .ok_or_else(|| invalid(format!("undefined value {:?}", name)))
+                })
+                .collect::<io::Result<Vec<&Tensor>>>()?;
+            let out = eval(node, &args)?;
+            values.insert(node.outputs[0].clone(), out);
+        }
+
+        let output = self
+            .outputs
+            .first()
+            .ok_or_else(|| invalid("the graph has no output".to_string()))?;
+        values
+            .remove(output)
+            .map(|tensor| tensor.data)
+            .ok_or_else(|| invalid(format!("output {:?} was never computed", output)))
+    }
+}
+
+fn eval(node: &Node, args: &[&Tensor]) -> io::Result<Tensor> {
+    let arity = match node.op_type.as_str() {
+        "Gemm" => 3,
+        "MatMul" | "Add" => 2,
+        "Tanh" | "Relu" | "Sigmoid" | "Softmax" | "Identity" => 1,
+        op => return Err(invalid(format!("unsupported op {}", op))),
+    };
+    if args.len() < arity.min(2) || args.len() > arity {
+        return Err(invalid(format!(
+            "{} takes {} inputs, got {}",
+            node.op_type,
+            arity,
+            args.len()
+        )));
+    }
+
+    let map = |f: fn(f64) -> f64| Tensor {
+        dims: args[0].dims.clone(),
+        data: args[0].data.iter().map(|x| f(*x)).collect(),
+    };
+
+    match node.op_type.as_str() {
+        "Gemm" => {
+            let attribute = |name: &str| node.attributes.get(name).copied().unwrap_or(0) != 0;
+            let product = matmul(args[0], attribute("transA"), args[1], attribute("transB"))?;
+            match args.get(2) {
+                Some(bias) => add(&product, bias),
+                None => Ok(product),
+            }
+        }
+        "MatMul" => matmul(args[0], false, args[1], false),
+        "Add" => add(args[0], args[1]),
+        "Tanh" => Ok(map(f64::tanh)),
+        "Relu" => Ok(map(|x| x.max(0.0))),
+        "Sigmoid" => Ok(map(|x| 1.0 / (1.0 + (-x).exp()))),
+        "Softmax" => {
+            let width = args[0].dims.last().copied().unwrap_or(1).max(1);
+            let data = args[0]
+                .data
+                .chunks(width)
+                .flat_map(|row| Activation::Softmax.apply_layer(row.to_vec()))
+                .collect();
+            Ok(Tensor {
+                dims: args[0].dims.clone(),
+                data,
+            })
+        }
+        _ => Ok(args[0].clone()),
+    }
+}
+
+/// Rows and columns of a rank 2 tensor, optionally transposed.
+fn shape(tensor: &Tensor, transpose: bool) -> io::Result<(usize, usize)> {
+    match tensor.dims.as_slice() {
+        [rows, cols] if transpose => Ok((*cols, *rows)),
+        [rows, cols] => Ok((*rows, *cols)),
+        dims => Err(invalid(format!("expected a matrix, got shape {:?}", dims))),
+    }
+}
+
+fn matmul(a: &Tensor, trans_a: bool, b: &Tensor, trans_b: bool) -> io::Result<Tensor> {
+    let (m, k) = shape(a, trans_a)?;
+    let (k2, n) = shape(b, trans_b)?;
+    if k != k2 {
+        return Err(invalid(format!(
+            "cannot multiply {}x{} by {}x{}",
+            m, k, k2, n
+        )));
+    }
+
+    let at = |i: usize, p: usize| {
+        if trans_a {
+            a.data[p * m + i]
+        } else {
+            a.data[i * k + p]
+        }
+    };
+    let bt = |p: usize, j: usize| {
+        if trans_b {
+            b.data[j * k + p]
+        } else {
+            b.data[p * n + j]
+        }
+    };
+
+    let mut data = Vec::with_capacity(m * n);
+    for i in 0..m {
+        for j in 0..n {
+            data.push((0..k).map(|p| at(i, p) * bt(p, j)).sum());
+        }
+    }
+    Ok(Tensor {
+        dims: vec![m, n],
+        data,
+    })
+}
+
+/// `a + b`, where `b` is either the shape of `a` or broadcast along its
+/// last dimension.
+fn add(a: &Tensor, b: &Tensor) -> io::Result<Tensor> {
+    let width = *a.dims.last().unwrap_or(&1);
+    let data = if b.dims == a.dims {
+        a.data.iter().zip(&b.data).map(|(x, y)| x + y).collect()
+    } else if b.data.len() == width {
+        a.data
+            .iter()
+            .enumerate()
+            .map(|(i, x)| x + b.data[i % width])
+            .collect()
+    } else {
+        return Err(invalid(format!(
+            "cannot add shape {:?} to {:?}",
+            b.dims, a.dims
+        )));
+    };
+
+    Ok(Tensor {
+        dims: a.dims.clone(),
+        data,
+    })
+}
+
+fn read_node(bytes: &[u8]) -> io::Result<Node> {
+    let mut node = Node {
+        op_type: String::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        attributes: HashMap::new(),
+    };
+
+    for field in fields(bytes) {
+        match field? {
+            (1, Field::Bytes(bytes)) => node.inputs.push(utf8(bytes)?),
+            (2, Field::Bytes(bytes)) => node.outputs.push(utf8(bytes)?),
+            (4, Field::Bytes(bytes)) => node.op_type = utf8(bytes)?,
+            (5, Field::Bytes(bytes)) => {
+                let (mut name, mut value) = (String::new(), 0);
+                for field in fields(bytes) {
+                    match field? {
+                        (1, Field::Bytes(bytes)) => name = utf8(bytes)?,
+                        (3, Field::Varint(i)) => value = i as i64,
+                        _ => {}
+                    }
+                }
+                node.attributes.insert(name, value);
+            }
+            _ => {}
+        }
+    }
+
+    if node.outputs.is_empty() {
+        return Err(invalid(format!("{} node has no output", node.op_type)));
+    }
+    Ok(node)
+}
+
+fn read_tensor(bytes: &[u8]) -> io::Result<(String, Tensor)> {
+    let (mut name, mut dims, mut data_type, mut raw) = (String::new(), Vec::new(), 0, None);
+    let mut doubles = Vec::new();
+
+    for field in fields(bytes) {
+        match field? {
+            (1, Field::Varint(dim)) => dims.push(dim as usize),
+            (1, Field::Bytes(packed)) => {
+                for field in varints(packed) {
+                    dims.push(field? as usize);
+                }
+            }
+            (2, Field::Varint(t)) => data_type = t,
+            (8, Field::Bytes(bytes)) => name = utf8(bytes)?,
+            (9, Field::Bytes(bytes)) => raw = Some(bytes),
+            (10, Field::Fixed64(bits)) => doubles.push(f64::from_bits(bits)),
+            (10, Field::Bytes(packed)) => doubles.extend(
+                packed
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+            ),
+            _ => {}
+        }
+    }
+
+    if data_type != DOUBLE {
+        return Err(invalid(format!(
+            "tensor {:?} has data type {}, only doubles are supported",
+            name, data_type
+        )));
+    }
+    let data = match raw {
+        Some(raw) => raw
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        None => doubles,
+    };
+    if data.len() != dims.iter().product::<usize>() {
+        return Err(invalid(format!(
+            "tensor {:?} has {} values for shape {:?}",
+            name,
+            data.len(),
+            dims
+        )));
+    }
+
+    Ok((name, Tensor { dims, data }))
+}
+
+fn read_name(value_info: &[u8]) -> io::Result<String> {
+    for field in fields(value_info) {
+        if let (1, Field::Bytes(bytes)) = field? {
+            return utf8(bytes);
+        }
+    }
+    Err(invalid("graph input or output without a name".to_string()))
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|err| invalid(err.to_string()))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    /// Skipped, as nothing the reader needs is 32-bit.
+    Fixed32,
+    Bytes(&'a [u8]),
+}
+
+/// The `(field number, value)` pairs of an encoded message.
+fn fields(mut bytes: &[u8]) -> impl Iterator<Item = io::Result<(u64, Field<'_>)>> {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(read_field(&mut bytes))
+    })
+}
+
+fn read_field<'a>(bytes: &mut &'a [u8]) -> io::Result<(u64, Field<'a>)> {
+    let key = read_varint(bytes)?;
+    let field = match key & 7 {
+        0 => Field::Varint(read_varint(bytes)?),
+        1 => Field::Fixed64(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap())),
+        2 => {
+            let len = read_varint(bytes)? as usize;
+            Field::Bytes(take(bytes, len)?)
+        }
+        5 => {
+            take(bytes, 4)?;
+            Field::Fixed32
+        }
+        wire_type => return Err(invalid(format!("unsupported wire type {}", wire_type))),
+    };
+    Ok((key >> 3, field))
+}
+
+fn varints(mut bytes: &[u8]) -> impl Iterator<Item = io::Result<u64>> + '_ {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(read_varint(&mut bytes))
+    })
+}
+
+fn read_varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *take(bytes, 1)?.first().unwrap();
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint is too long".to_string()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if bytes.len() < n {
+        return Err(invalid("truncated protobuf message".to_string()));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::to_values, random};
+
+    fn assert_runs_like(model: &MLP, onnx: &OnnxModel) {
+        for x in [[0.5, -1.0, 2.0], [0.0, 0.0, 0.0], [-3.0, 0.25, 1.5]] {
+            let expected = model
+                .call(&to_values(&x))
+                .iter()
+                .map(|v| v.borrow().data)
+                .collect::<Vec<f64>>();
+            let actual = onnx.run(&x).unwrap();
+
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(&expected) {
+                assert!((a - e).abs() < 1e-12, "{} != {}", a, e);
+            }
+        }
+    }
+
+    #[test]
+    fn exported_graph_matches_mlp_call() {
+        random::seed(42);
+        let model = MLP::new(3, vec![5, 4, 2]);
+        let onnx = OnnxModel::from_bytes(&model.to_onnx()).unwrap();
+
+        assert_eq!(onnx.inputs, ["input"]);
+        assert_eq!(onnx.outputs, ["output"]);
+        assert_eq!(onnx.initializers.len(), 6);
+        assert_eq!(onnx.initializers["layers.1.weight"].dims, [4, 5]);
+
+        assert_runs_like(&model, &onnx);
+    }
+
+    #[test]
+    fn sigmoid_and_softmax_heads_round_trip() {
+        random::seed(43);
+        let sigmoid =
+            MLP::new(3, vec![4, 1]).activations(vec![Activation::ReLU, Activation::Sigmoid]);
+        let softmax = MLP::new(3, vec![4, 3]).output_activation(Activation::Softmax);
+
+        for model in [sigmoid, softmax] {
+            let onnx = OnnxModel::from_bytes(&model.to_onnx()).unwrap();
+            let last = onnx.nodes.last().unwrap();
+            assert_eq!(last.outputs, ["output"]);
+            assert_ne!(last.op_type, "Gemm");
+            assert_runs_like(&model, &onnx);
+        }
+    }
+
+    #[test]
+    fn unsupported_ops_are_errors() {
+        let mut onnx = OnnxModel::from_bytes(&MLP::new(3, vec![2]).to_onnx()).unwrap();
+        onnx.nodes.push(Node {
+            op_type: "Conv".to_string(),
+            inputs: vec!["output".to_string()],
+            outputs: vec!["convolved".to_string()],
+            attributes: HashMap::new(),
+        });
+        onnx.outputs = vec!["convolved".to_string()];
+
+        let err = onnx.run(&[1.0, 2.0, 3.0]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unsupported op Conv");
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::{self, ErrorKind};
+
+use crate::engine::*;
+
+/// What an optimizer carries from one step to the next, for checkpoints.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptimizerState {
+    Stateless,
+    Sgd {
+        lr: f64,
+        momentum: f64,
+        velocity: Vec<f64>,
+    },
+    Adam {
+        lr: f64,
+        beta1: f64,
+        beta2: f64,
+        eps: f64,
+        m: Vec<f64>,
+        v: Vec<f64>,
+        t: u64,
+    },
+}
+
+/// Updates parameters in place from their accumulated gradients.
+///
+/// Optimizers that keep per-parameter state index it by position, so they
+/// must always be stepped with the same `parameters()` list. Parameters that
+/// don't require a gradient are left untouched.
+pub trait Optimizer {
+    fn step(&mut self, params: &[Value]);
+
+    fn zero_grad(&self, params: &[Value]) {
+        for p in params {
+            p.0.borrow_mut().grad = 0.0;
+        }
+    }
+
+    /// Sets the learning rate, for `LrScheduler`. Optimizers without one
+    /// ignore it.
+    fn set_lr(&mut self, _lr: f64) {}
+
+    /// The hyperparameters and per-parameter buffers, for checkpoints.
+    fn state(&self) -> OptimizerState {
+        OptimizerState::Stateless
+    }
+
+    /// Restores what `state` returned. Fails on the state of another kind of
+    /// optimizer.
+    fn load_state(&mut self, state: OptimizerState) -> io::Result<()> {
+        match state {
+            OptimizerState::Stateless => Ok(()),
+            other => Err(mismatch(&other)),
+        }
+    }
+}
+
+fn mismatch(state: &OptimizerState) -> io::Error {
+    let kind = match state {
+        OptimizerState::Stateless => "stateless",
+        OptimizerState::Sgd { .. } => "sgd",
+        OptimizerState::Adam { .. } => "adam",
+    };
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("the checkpoint holds {} optimizer state", kind),
+    )
+}
+
+/// Stochastic gradient descent with optional momentum.
+pub struct Sgd {
+    pub lr: f64,
+    pub momentum: f64,
+    pub velocity: Vec<f64>,
+}
+
+impl Sgd {
+    pub fn new(lr: f64) -> Self {
+        Sgd::with_momentum(lr, 0.0)
+    }
+
+    pub fn with_momentum(lr: f64, momentum: f64) -> Self {
+        Sgd {
+            lr,
+            momentum,
+            velocity: Vec::new(),
+        }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self, params: &[Value]) {
+        if self.velocity.len() != params.len() {
+            self.velocity = vec![0.0; params.len()];
+        }
+
+        for (p, v) in params.iter().zip(self.velocity.iter_mut()) {
+            if !p.requires_grad() {
+                continue;
+            }
+            let grad = p.0.borrow().grad;
+            *v = self.momentum * *v + grad;
+            p.0.borrow_mut().data -= self.lr * *v;
+        }
+    }
+
+    fn state(&self) -> OptimizerState {
+        OptimizerState::Sgd {
+            lr: self.lr,
+            momentum: self.momentum,
+            velocity: self.velocity.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: OptimizerState) -> io::Result<()> {
+        match state {
+            OptimizerState::Sgd {
+                lr,
+                momentum,
+                velocity,
+            } => {
+                self.lr = lr;
+                self.momentum = momentum;
+                self.velocity = velocity;
+                Ok(())
+            }
+            other => Err(mismatch(&other)),
+        }
+    }
+}
+
+/// Adam, with bias-corrected first and second moment estimates.
+pub struct Adam {
+    pub lr: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+    pub m: Vec<f64>,
+    pub v: Vec<f64>,
+    pub t: u64,
+}
+
+impl Adam {
+    pub fn new(lr: f64) -> Self {
+        Adam {
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            m: Vec::new(),
+            v: Vec::new(),
+            t: 0,
+        }
+    }
+}
+
+impl Optimizer for Adam {
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self, params: &[Value]) {
+        if self.m.len() != params.len() {
+            self.m = vec![0.0; params.len()];
+            self.v = vec![0.0; params.len()];
+            self.t = 0;
+        }
+
+        self.t += 1;
+        let bias1 = 1.0 - self.beta1.powi(self.t as i32);
+        let bias2 = 1.0 - self.beta2.powi(self.t as i32);
+
+        for (i, p) in params.iter().enumerate() {
+            if !p.requires_grad() {
+                continue;
+            }
+            let grad = p.0.borrow().grad;
+            self.m[i] = self.beta1 * self.m[i] + (1.0 - self.beta1) * grad;
+            self.v[i] = self.beta2 * self.v[i] + (1.0 - self.beta2) * grad * grad;
+
+            let m_hat = self.m[i] / bias1;
+            let v_hat = self.v[i] / bias2;
+            p.0.borrow_mut().data -= self.lr * m_hat / (v_hat.sqrt() + self.eps);
+        }
+    }
+
+    fn state(&self) -> OptimizerState {
+        OptimizerState::Adam {
+            lr: self.lr,
+            beta1: self.beta1,
+            beta2: self.beta2,
+            eps: self.eps,
+            m: self.m.clone(),
+            v: self.v.clone(),
+            t: self.t,
+        }
+    }
+
+    fn load_state(&mut self, state: OptimizerState) -> io::Result<()> {
+        match state {
+            OptimizerState::Adam {
+                lr,
+                beta1,
+                beta2,
+                eps,
+                m,
+                v,
+                t,
+            } => {
+                *self = Adam {
+                    lr,
+                    beta1,
+                    beta2,
+                    eps,
+                    m,
+                    v,
+                    t,
+                };
+                Ok(())
+            }
+            other => Err(mismatch(&other)),
+        }
+    }
+}
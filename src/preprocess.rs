@@ -0,0 +1,384 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{Dataset, InMemoryDataset},
+    module::Module,
+};
+
+/// A feature transform whose parameters are learned by `fit` and then
+/// reapplied unchanged by `transform`.
+pub trait Transform {
+    fn fit(&mut self, rows: &[Vec<f64>]);
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError>;
+
+    fn transform(&self, rows: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, TransformError> {
+        rows.iter().map(|row| self.transform_row(row)).collect()
+    }
+
+    fn fit_transform(&mut self, rows: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, TransformError> {
+        self.fit(rows);
+        self.transform(rows)
+    }
+}
+
+/// Input that a fitted transform can't map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformError {
+    /// A `LabelEncoder` got a label that `fit` never saw.
+    UnseenLabel(f64),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::UnseenLabel(label) => {
+                write!(f, "label {} was not seen by fit", label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Scales every column to zero mean and unit variance. Constant columns are
+/// only centered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StandardScaler {
+    pub mean: Vec<f64>,
+    pub std: Vec<f64>,
+}
+
+impl StandardScaler {
+    pub fn new() -> Self {
+        StandardScaler::default()
+    }
+
+    pub fn inverse_transform_row(&self, row: &[f64]) -> Vec<f64> {
+        row.iter()
+            .zip(self.mean.iter().zip(&self.std))
+            .map(|(x, (mean, std))| x * std + mean)
+            .collect()
+    }
+}
+
+impl Transform for StandardScaler {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        assert!(!rows.is_empty(), "cannot fit a scaler on zero rows");
+
+        let n = rows.len() as f64;
+        self.mean = columns(rows)
+            .map(|column| column.sum::<f64>() / n)
+            .collect();
+        self.std = columns(rows)
+            .zip(&self.mean)
+            .map(|(column, mean)| {
+                let std = (column.map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+                if std == 0.0 {
+                    1.0
+                } else {
+                    std
+                }
+            })
+            .collect();
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        assert_eq!(row.len(), self.mean.len(), "row width differs from fit");
+
+        Ok(row
+            .iter()
+            .zip(self.mean.iter().zip(&self.std))
+            .map(|(x, (mean, std))| (x - mean) / std)
+            .collect())
+    }
+}
+
+/// Scales every column linearly onto `range`, from the min and max seen by
+/// `fit`. Constant columns map to the lower end of the range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinMaxScaler {
+    pub range: (f64, f64),
+    pub min: Vec<f64>,
+    pub max: Vec<f64>,
+}
+
+impl MinMaxScaler {
+    pub fn new(range: (f64, f64)) -> Self {
+        assert!(range.0 < range.1, "range must be increasing");
+        MinMaxScaler {
+            range,
+            min: Vec::new(),
+            max: Vec::new(),
+        }
+    }
+}
+
+impl Default for MinMaxScaler {
+    fn default() -> Self {
+        MinMaxScaler::new((0.0, 1.0))
+    }
+}
+
+impl Transform for MinMaxScaler {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        assert!(!rows.is_empty(), "cannot fit a scaler on zero rows");
+
+        self.min = columns(rows)
+            .map(|column| column.fold(f64::INFINITY, f64::min))
+            .collect();
+        self.max = columns(rows)
+            .map(|column| column.fold(f64::NEG_INFINITY, f64::max))
+            .collect();
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        assert_eq!(row.len(), self.min.len(), "row width differs from fit");
+
+        let (low, high) = self.range;
+        Ok(row
+            .iter()
+            .zip(self.min.iter().zip(&self.max))
+            .map(|(x, (min, max))| {
+                let span = if max > min { max - min } else { 1.0 };
+                low + (x - min) / span * (high - low)
+            })
+            .collect())
+    }
+}
+
+/// Replaces each of `columns` with one indicator column per category seen
+/// by `fit`, in place. Categories not seen by `fit` encode as all zeros.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OneHotEncoder {
+    pub columns: Vec<usize>,
+    pub categories: Vec<Vec<f64>>,
+}
+
+impl OneHotEncoder {
+    pub fn new(columns: Vec<usize>) -> Self {
+        OneHotEncoder {
+            columns,
+            categories: Vec::new(),
+        }
+    }
+}
+
+impl Transform for OneHotEncoder {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        self.categories = self
+            .columns
+            .iter()
+            .map(|c| {
+                let mut categories = rows.iter().map(|row| row[*c]).collect::<Vec<f64>>();
+                categories.sort_by(f64::total_cmp);
+                categories.dedup();
+                categories
+            })
+            .collect();
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        let mut out = Vec::with_capacity(row.len());
+        for (i, x) in row.iter().enumerate() {
+            match self.columns.iter().position(|c| *c == i) {
+                Some(k) => out.extend(self.categories[k].iter().map(|c| (c == x) as u8 as f64)),
+                None => out.push(*x),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Maps the distinct values of a single-column target to class indices
+/// `0..n_classes`, in ascending order of value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LabelEncoder {
+    pub classes: Vec<f64>,
+}
+
+impl LabelEncoder {
+    pub fn new() -> Self {
+        LabelEncoder::default()
+    }
+
+    /// The class of `label`, or `None` if `fit` never saw it.
+    pub fn encode(&self, label: f64) -> Option<usize> {
+        self.classes.iter().position(|c| *c == label)
+    }
+
+    pub fn decode(&self, class: usize) -> f64 {
+        self.classes[class]
+    }
+}
+
+impl Transform for LabelEncoder {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        let mut classes = rows.iter().map(|row| row[0]).collect::<Vec<f64>>();
+        classes.sort_by(f64::total_cmp);
+        classes.dedup();
+        self.classes = classes;
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        match self.encode(row[0]) {
+            Some(class) => Ok(vec![class as f64]),
+            None => Err(TransformError::UnseenLabel(row[0])),
+        }
+    }
+}
+
+/// The transforms a `Pipeline` can hold. An enum rather than trait objects so
+/// that pipelines serialize.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Preprocessor {
+    Standard(StandardScaler),
+    MinMax(MinMaxScaler),
+    OneHot(OneHotEncoder),
+    Label(LabelEncoder),
+}
+
+impl Preprocessor {
+    fn inner(&self) -> &dyn Transform {
+        match self {
+            Preprocessor::Standard(t) => t,
+            Preprocessor::MinMax(t) => t,
+            Preprocessor::OneHot(t) => t,
+            Preprocessor::Label(t) => t,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn Transform {
+        match self {
+            Preprocessor::Standard(t) => t,
+            Preprocessor::MinMax(t) => t,
+            Preprocessor::OneHot(t) => t,
+            Preprocessor::Label(t) => t,
+        }
+    }
+}
+
+impl Transform for Preprocessor {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        self.inner_mut().fit(rows)
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        self.inner().transform_row(row)
+    }
+}
+
+/// Transforms applied in order, each fit on the output of the previous one.
+///
+/// Fit it on the training inputs, then save it with the model through
+/// `MLP::save_json_with_pipeline` so inference goes through exactly the same
+/// transforms.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub steps: Vec<Preprocessor>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn then(mut self, step: Preprocessor) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Transforms the inputs of `dataset`, leaving its targets as they are.
+    pub fn transform_dataset(
+        &self,
+        dataset: &dyn Dataset,
+    ) -> Result<InMemoryDataset, TransformError> {
+        let InMemoryDataset { xs, ys } = InMemoryDataset::collect(dataset);
+        Ok(InMemoryDataset::new(self.transform(&xs)?, ys))
+    }
+
+    /// Runs `model` on the transformed `row`.
+    pub fn predict(&self, model: &dyn Module, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        Ok(model.predict(&self.transform_row(row)?))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> io::Result<Self> {
+        Pipeline::from_json(&fs::read_to_string(path)?).map_err(io::Error::from)
+    }
+}
+
+impl Transform for Pipeline {
+    fn fit(&mut self, rows: &[Vec<f64>]) {
+        let mut rows = rows.to_vec();
+        for step in self.steps.iter_mut() {
+            rows = step
+                .fit_transform(&rows)
+                .expect("a fitted step maps the rows it was fit on");
+        }
+    }
+
+    fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, TransformError> {
+        self.steps
+            .iter()
+            .try_fold(row.to_vec(), |row, step| step.transform_row(&row))
+    }
+}
+
+/// Iterates over the columns of `rows`.
+fn columns(rows: &[Vec<f64>]) -> impl Iterator<Item = impl Iterator<Item = f64> + '_> + '_ {
+    (0..rows[0].len()).map(move |c| rows.iter().map(move |row| row[c]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mlp::MLP, module::Sequential, random};
+
+    #[test]
+    fn unseen_labels_are_errors() {
+        let mut pipeline = Pipeline::new().then(Preprocessor::Label(LabelEncoder::new()));
+        pipeline.fit(&[vec![3.0], vec![7.0]]);
+
+        let model = Sequential::new();
+        assert_eq!(pipeline.predict(&model, &[7.0]), Ok(vec![1.0]));
+        assert_eq!(
+            pipeline.predict(&model, &[5.0]),
+            Err(TransformError::UnseenLabel(5.0))
+        );
+    }
+
+    #[test]
+    fn pipeline_is_stored_with_the_model() {
+        random::seed(7);
+        let model = MLP::new(2, vec![3, 1]);
+        let mut pipeline = Pipeline::new().then(Preprocessor::Standard(StandardScaler::new()));
+        pipeline.fit(&[vec![1.0, 10.0], vec![3.0, 30.0]]);
+
+        let json = model.to_json_with_pipeline(&pipeline);
+        let (loaded, loaded_pipeline) = MLP::from_json_with_pipeline(&json).unwrap();
+        assert_eq!(loaded_pipeline.as_ref(), Some(&pipeline));
+        assert_eq!(
+            loaded_pipeline.unwrap().predict(&loaded, &[2.0, 5.0]),
+            pipeline.predict(&model, &[2.0, 5.0])
+        );
+
+        assert_eq!(
+            MLP::from_json_with_pipeline(&model.to_json()).unwrap().1,
+            None
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+// The generator behind everything in the crate that draws random numbers
+// without being handed an RNG, e.g. `Neuron::new`. It starts from entropy;
+// call `seed` first to make a run reproducible.
+//
+// `ChaCha12Rng` is the algorithm behind `StdRng`, used directly because its
+// position can be read back and restored (see `RngState`).
+thread_local! {
+    static RNG: RefCell<ChaCha12Rng> = RefCell::new(ChaCha12Rng::from_entropy());
+}
+
+/// Reseeds the crate-wide generator of the current thread.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = ChaCha12Rng::seed_from_u64(seed));
+}
+
+/// Runs `f` with the crate-wide generator of the current thread.
+pub fn with_rng<T>(f: impl FnOnce(&mut ChaCha12Rng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// The state of the crate-wide generator of the current thread.
+pub fn state() -> RngState {
+    with_rng(|rng| RngState::of(rng))
+}
+
+pub fn set_state(state: &RngState) {
+    RNG.with(|rng| *rng.borrow_mut() = state.rng());
+}
+
+/// Everything needed to continue a `ChaCha12Rng` stream exactly where it
+/// was: the seed, the stream and the position in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+}
+
+impl RngState {
+    pub fn of(rng: &ChaCha12Rng) -> Self {
+        RngState {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+
+    pub fn rng(&self) -> ChaCha12Rng {
+        let mut rng = ChaCha12Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
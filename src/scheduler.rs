@@ -0,0 +1,60 @@
+use std::f64::consts::PI;
+
+/// How the learning rate changes from one epoch to the next, as a factor of
+/// the initial rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    Constant,
+    /// Multiplies the rate by `gamma` every `step_size` epochs.
+    Step {
+        step_size: u64,
+        gamma: f64,
+    },
+    /// Multiplies the rate by `gamma` every epoch.
+    Exponential(f64),
+    /// Anneals from the initial rate down to `min_lr` over `period` epochs
+    /// along half a cosine, then stays at `min_lr`.
+    Cosine {
+        period: u64,
+        min_lr: f64,
+    },
+}
+
+/// Sets the learning rate of the optimizer in `Trainer::fit`, one step per
+/// epoch. Its position, `epoch`, is saved in checkpoints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrScheduler {
+    pub base_lr: f64,
+    pub schedule: Schedule,
+    /// Steps taken, i.e. epochs completed.
+    pub epoch: u64,
+}
+
+impl LrScheduler {
+    pub fn new(base_lr: f64, schedule: Schedule) -> Self {
+        LrScheduler {
+            base_lr,
+            schedule,
+            epoch: 0,
+        }
+    }
+
+    /// The learning rate for the current epoch.
+    pub fn lr(&self) -> f64 {
+        match self.schedule {
+            Schedule::Constant => self.base_lr,
+            Schedule::Step { step_size, gamma } => {
+                self.base_lr * gamma.powi((self.epoch / step_size.max(1)) as i32)
+            }
+            Schedule::Exponential(gamma) => self.base_lr * gamma.powi(self.epoch as i32),
+            Schedule::Cosine { period, min_lr } => {
+                let progress = self.epoch.min(period) as f64 / period.max(1) as f64;
+                min_lr + (self.base_lr - min_lr) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.epoch += 1;
+    }
+}
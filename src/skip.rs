@@ -0,0 +1,336 @@
+use rand::Rng;
+
+use crate::{
+    activation::Activation,
+    engine::*,
+    init::ParamInit,
+    mlp::{prefixed, Layer},
+    module::Module,
+};
+
+/// A residual block computing `x + f(x)`, where `f` is `body`. The shortcut
+/// gives gradients a path around `body`, so deep stacks of tanh layers keep
+/// training.
+///
+/// When `body` changes the width, the shortcut needs a linear `projection`
+/// from the input width to the output width.
+pub struct Residual {
+    pub body: Box<dyn Module>,
+    pub projection: Option<Layer>,
+}
+
+impl Residual {
+    pub fn new(body: impl Module + 'static) -> Self {
+        Residual {
+            body: Box::new(body),
+            projection: None,
+        }
+    }
+
+    /// Projects the shortcut from `nin` to `nout` with a linear `Layer`
+    /// drawn as `Layer::with_init` draws it.
+    pub fn projection<R: Rng + ?Sized>(
+        mut self,
+        nin: i32,
+        nout: i32,
+        init: &ParamInit,
+        rng: &mut R,
+    ) -> Self {
+        let mut layer = Layer::with_init(nin, nout, init, rng);
+        layer.set_activation(Activation::Linear);
+        self.projection = Some(layer);
+        self
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let shortcut = match &self.projection {
+            Some(projection) => projection.call(inputs),
+            None => inputs.to_vec(),
+        };
+        add(shortcut, self.body.forward(inputs))
+    }
+
+    pub fn call_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        let shortcuts = match &self.projection {
+            Some(projection) => projection.call_batch(batch),
+            None => batch.to_vec(),
+        };
+        shortcuts
+            .into_iter()
+            .zip(self.body.forward_batch(batch))
+            .map(|(shortcut, outputs)| add(shortcut, outputs))
+            .collect()
+    }
+
+    /// `call` on plain floats, in eval mode.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let shortcut = match &self.projection {
+            Some(projection) => projection.predict(inputs),
+            None => inputs.to_vec(),
+        };
+        let outputs = self.body.predict(inputs);
+        check_widths(shortcut.len(), outputs.len());
+        shortcut.iter().zip(&outputs).map(|(x, y)| x + y).collect()
+    }
+
+    /// The parameters of `body`, then those of `projection`.
+    pub fn parameters(&self) -> Vec<Value> {
+        let mut params = self.body.parameters();
+        if let Some(projection) = &self.projection {
+            params.extend(projection.parameters());
+        }
+        params
+    }
+
+    /// `parameters()` with their paths, `body.{name}` and
+    /// `projection.{name}`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        let mut named = prefixed("body", self.body.named_parameters());
+        if let Some(projection) = &self.projection {
+            named.extend(prefixed("projection", projection.named_parameters()));
+        }
+        named
+    }
+}
+
+/// A dense skip connection: outputs the inputs followed by the outputs of
+/// `body`, `[x, f(x)]`, as in DenseNet. Later layers see every earlier
+/// feature, so the next layer takes `nin + nout` inputs.
+pub struct DenseSkip {
+    pub body: Box<dyn Module>,
+}
+
+impl DenseSkip {
+    pub fn new(body: impl Module + 'static) -> Self {
+        DenseSkip {
+            body: Box::new(body),
+        }
+    }
+
+    pub fn call(&self, inputs: &[Value]) -> Vec<Value> {
+        let mut outputs = inputs.to_vec();
+        outputs.extend(self.body.forward(inputs));
+        outputs
+    }
+
+    pub fn call_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        batch
+            .iter()
+            .zip(self.body.forward_batch(batch))
+            .map(|(x, outputs)| x.iter().cloned().chain(outputs).collect())
+            .collect()
+    }
+
+    /// `call` on plain floats, in eval mode.
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let mut outputs = inputs.to_vec();
+        outputs.extend(self.body.predict(inputs));
+        outputs
+    }
+
+    pub fn parameters(&self) -> Vec<Value> {
+        self.body.parameters()
+    }
+
+    /// `parameters()` with their paths, `body.{name}`.
+    pub fn named_parameters(&self) -> Vec<(String, Value)> {
+        prefixed("body", self.body.named_parameters())
+    }
+}
+
+impl Module for Residual {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        self.call_batch(batch)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        Residual::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Residual::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        Residual::named_parameters(self)
+    }
+
+    fn buffers(&self) -> Vec<f64> {
+        self.body.buffers()
+    }
+
+    fn load_buffers(&self, buffers: &[f64]) {
+        self.body.load_buffers(buffers)
+    }
+
+    fn train(&self) {
+        self.body.train()
+    }
+
+    fn eval(&self) {
+        self.body.eval()
+    }
+}
+
+impl Module for DenseSkip {
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.call(inputs)
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        self.call_batch(batch)
+    }
+
+    fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        DenseSkip::predict(self, inputs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        DenseSkip::parameters(self)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        DenseSkip::named_parameters(self)
+    }
+
+    fn buffers(&self) -> Vec<f64> {
+        self.body.buffers()
+    }
+
+    fn load_buffers(&self, buffers: &[f64]) {
+        self.body.load_buffers(buffers)
+    }
+
+    fn train(&self) {
+        self.body.train()
+    }
+
+    fn eval(&self) {
+        self.body.eval()
+    }
+}
+
+fn add(shortcut: Vec<Value>, outputs: Vec<Value>) -> Vec<Value> {
+    check_widths(shortcut.len(), outputs.len());
+    shortcut
+        .into_iter()
+        .zip(outputs)
+        .map(|(x, y)| x + y)
+        .collect()
+}
+
+fn check_widths(shortcut: usize, outputs: usize) {
+    assert_eq!(
+        shortcut, outputs,
+        "residual shortcut has width {} but the body outputs {}, add a projection",
+        shortcut, outputs
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+    use crate::init::Init;
+
+    fn leaves(data: &[f64]) -> Vec<Value> {
+        data.iter().map(|x| Value::new(*x, "x")).collect()
+    }
+
+    fn data(values: &[Value]) -> Vec<f64> {
+        values.iter().map(|v| v.borrow().data).collect()
+    }
+
+    /// A tanh layer seeded with `seed`, and the same layer again.
+    fn layer_pair(nin: i32, nout: i32, seed: u64) -> (Layer, Layer) {
+        let layer = || Layer::with_rng(nin, nout, &mut ChaCha12Rng::seed_from_u64(seed));
+        (layer(), layer())
+    }
+
+    #[test]
+    fn residual_adds_the_input_to_the_body() {
+        let (body, copy) = layer_pair(3, 3, 7);
+        let block = Residual::new(body);
+
+        let x = leaves(&[0.5, -1.0, 2.0]);
+        let expected = data(&copy.call(&x))
+            .iter()
+            .zip(data(&x))
+            .map(|(f, x)| f + x)
+            .collect::<Vec<f64>>();
+        assert_eq!(data(&block.call(&x)), expected);
+        assert_eq!(block.predict(&data(&x)), expected);
+        assert_eq!(data(&block.call_batch(&[x])[0]), expected);
+
+        // with a zero body only the shortcut carries the gradient
+        let zeros = ParamInit::new(Init::Zeros, Init::Zeros);
+        let mut rng = ChaCha12Rng::seed_from_u64(7);
+        let block = Residual::new(Layer::with_init(3, 3, &zeros, &mut rng));
+        let x = leaves(&[0.5, -1.0, 2.0]);
+        block.call(&x)[1].clone().backward();
+        let grads = x.iter().map(|x| x.borrow().grad).collect::<Vec<f64>>();
+        assert_eq!(grads, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn the_projection_maps_the_shortcut_to_the_body_width() {
+        let (body, copy) = layer_pair(3, 2, 7);
+        let ones = ParamInit::new(Init::Constant(1.0), Init::Zeros);
+        let mut rng = ChaCha12Rng::seed_from_u64(8);
+        let block = Residual::new(body).projection(3, 2, &ones, &mut rng);
+
+        let projection = block.projection.as_ref().unwrap();
+        assert_eq!((projection.nin(), projection.nout()), (3, 2));
+        assert_eq!(projection.activation(), Activation::Linear);
+
+        // each projected output is the sum of the inputs
+        let x = leaves(&[0.5, -1.0, 2.0]);
+        let expected = data(&copy.call(&x))
+            .iter()
+            .map(|f| f + 1.5)
+            .collect::<Vec<f64>>();
+        assert_eq!(data(&block.call(&x)), expected);
+        assert_eq!(block.predict(&data(&x)), expected);
+
+        assert_eq!(block.parameters().len(), 8 + 8);
+        let named = block.named_parameters();
+        assert_eq!(named[0].0, "body.neurons.0.w.0");
+        assert_eq!(named[8].0, "projection.neurons.0.w.0");
+
+        // drawn from the rng like any other layer
+        let mut rng = ChaCha12Rng::seed_from_u64(8);
+        let block =
+            Residual::new(layer_pair(3, 2, 7).0).projection(3, 2, &ParamInit::default(), &mut rng);
+        let mut rng = ChaCha12Rng::seed_from_u64(8);
+        let layer = Layer::with_rng(3, 2, &mut rng);
+        assert_eq!(block.projection.unwrap().weights(), layer.weights());
+    }
+
+    #[test]
+    #[should_panic(expected = "residual shortcut has width 3 but the body outputs 2")]
+    fn a_width_change_without_projection_panics() {
+        let block = Residual::new(layer_pair(3, 2, 7).0);
+        block.call(&leaves(&[0.5, -1.0, 2.0]));
+    }
+
+    #[test]
+    fn dense_skip_puts_the_inputs_before_the_body_outputs() {
+        let (body, copy) = layer_pair(3, 2, 7);
+        let block = DenseSkip::new(body);
+
+        let x = leaves(&[0.5, -1.0, 2.0]);
+        let mut expected = data(&x);
+        expected.extend(data(&copy.call(&x)));
+
+        assert_eq!(data(&block.call(&x)), expected);
+        assert_eq!(block.predict(&data(&x)), expected);
+        assert_eq!(data(&block.call_batch(&[x])[0]), expected);
+        assert_eq!(block.named_parameters()[0].0, "body.neurons.0.w.0");
+    }
+}
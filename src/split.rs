@@ -0,0 +1,358 @@
+use std::{collections::BTreeMap, fmt};
+
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    data::{DataLoader, Dataset},
+    loss::Loss,
+    metrics::Metric,
+    module::Module,
+    optim::Optimizer,
+    trainer::Trainer,
+};
+
+/// A view of some samples of another dataset, in the order of `indices`.
+pub struct Subset<'a> {
+    pub dataset: &'a dyn Dataset,
+    pub indices: Vec<usize>,
+}
+
+impl Dataset for Subset<'_> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        self.dataset.get(self.indices[index])
+    }
+}
+
+pub struct Split<'a> {
+    pub train: Subset<'a>,
+    pub val: Subset<'a>,
+    pub test: Subset<'a>,
+}
+
+/// Shuffles the samples and splits off `val_ratio` and `test_ratio` of them,
+/// leaving the rest for training. With `stratify` every distinct target is
+/// split separately, so each part keeps the class proportions.
+pub fn train_val_test_split(
+    dataset: &dyn Dataset,
+    val_ratio: f64,
+    test_ratio: f64,
+    stratify: bool,
+    seed: u64,
+) -> Split<'_> {
+    assert!(
+        val_ratio >= 0.0 && test_ratio >= 0.0 && val_ratio + test_ratio <= 1.0,
+        "split ratios must be non-negative and sum to at most 1"
+    );
+
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let (mut train, mut val, mut test) = (Vec::new(), Vec::new(), Vec::new());
+
+    for group in groups(dataset, stratify, &mut rng) {
+        let n_test = (group.len() as f64 * test_ratio).round() as usize;
+        let n_val = ((group.len() as f64 * val_ratio).round() as usize).min(group.len() - n_test);
+
+        test.extend_from_slice(&group[..n_test]);
+        val.extend_from_slice(&group[n_test..n_test + n_val]);
+        train.extend_from_slice(&group[n_test + n_val..]);
+    }
+
+    for part in [&mut train, &mut val, &mut test] {
+        part.shuffle(&mut rng);
+    }
+
+    let subset = |indices| Subset { dataset, indices };
+    Split {
+        train: subset(train),
+        val: subset(val),
+        test: subset(test),
+    }
+}
+
+/// Splits the samples into `k` folds and returns `(train, validation)` for
+/// each, where the validation part is the fold itself. With `stratify` the
+/// samples of each distinct target are dealt over the folds in turn.
+pub fn k_fold(
+    dataset: &dyn Dataset,
+    k: usize,
+    stratify: bool,
+    seed: u64,
+) -> Vec<(Subset<'_>, Subset<'_>)> {
+    assert!(
+        k >= 2 && k <= dataset.len(),
+        "k must be between 2 and the number of samples"
+    );
+
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let mut folds = vec![Vec::new(); k];
+
+    let mut next = 0;
+    for group in groups(dataset, stratify, &mut rng) {
+        for index in group {
+            folds[next % k].push(index);
+            next += 1;
+        }
+    }
+
+    (0..k)
+        .map(|i| {
+            let train = folds
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, fold)| fold.iter().copied())
+                .collect();
+            (
+                Subset {
+                    dataset,
+                    indices: train,
+                },
+                Subset {
+                    dataset,
+                    indices: folds[i].clone(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Shuffled sample indices, grouped by target when stratifying.
+fn groups(dataset: &dyn Dataset, stratify: bool, rng: &mut ChaCha12Rng) -> Vec<Vec<usize>> {
+    let mut groups = if stratify {
+        let mut by_target = BTreeMap::<Vec<u64>, Vec<usize>>::new();
+        for i in 0..dataset.len() {
+            let (_, y) = dataset.get(i);
+            let key = y.iter().map(|v| v.to_bits()).collect();
+            by_target.entry(key).or_default().push(i);
+        }
+        by_target.into_values().collect()
+    } else {
+        vec![(0..dataset.len()).collect()]
+    };
+
+    for group in groups.iter_mut() {
+        group.shuffle(rng);
+    }
+    groups
+}
+
+/// Why `CrossValidation::run` can't split a dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CvError {
+    /// Fewer samples than folds, which would leave a fold empty.
+    TooFewSamples { k: usize, samples: usize },
+}
+
+impl fmt::Display for CvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CvError::TooFewSamples { k, samples } => write!(
+                f,
+                "{}-fold cross-validation needs at least {} samples, got {}",
+                k, k, samples
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CvError {}
+
+#[derive(Debug, Clone)]
+pub struct CvScores {
+    pub scores: Vec<f64>,
+    pub mean: f64,
+    pub std: f64,
+}
+
+/// K-fold cross-validation: for each fold a fresh model and optimizer are
+/// trained on the other folds, and `metric` is measured on the held-out fold.
+pub struct CrossValidation {
+    pub k: usize,
+    pub stratify: bool,
+    pub seed: u64,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub loss: Loss,
+    pub metric: Metric,
+}
+
+impl CrossValidation {
+    pub fn new(k: usize, loss: Loss, metric: Metric) -> Self {
+        assert!(k >= 2, "cross-validation needs at least 2 folds");
+        CrossValidation {
+            k,
+            stratify: false,
+            seed: 0,
+            epochs: 100,
+            batch_size: 32,
+            loss,
+            metric,
+        }
+    }
+
+    pub fn stratify(mut self, stratify: bool) -> Self {
+        self.stratify = stratify;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size must be at least 1");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Fails before training anything if `dataset` has fewer samples than
+    /// folds.
+    pub fn run<O, M, N, F>(
+        &self,
+        dataset: &dyn Dataset,
+        new_model: N,
+        new_optimizer: F,
+    ) -> Result<CvScores, CvError>
+    where
+        O: Optimizer,
+        M: Module,
+        N: Fn() -> M,
+        F: Fn() -> O,
+    {
+        if dataset.len() < self.k {
+            return Err(CvError::TooFewSamples {
+                k: self.k,
+                samples: dataset.len(),
+            });
+        }
+
+        let scores = k_fold(dataset, self.k, self.stratify, self.seed)
+            .iter()
+            .enumerate()
+            .map(|(fold, (train, val))| {
+                let model = new_model();
+                let mut loader =
+                    DataLoader::new(train, self.batch_size).shuffle(self.seed + fold as u64);
+
+                let mut trainer = Trainer::new(&model, self.loss, new_optimizer())
+                    .epochs(self.epochs)
+                    .metrics(vec![self.metric]);
+                trainer.fit(&mut loader, None, &mut []);
+
+                trainer.evaluate(val)[&self.metric.name()]
+            })
+            .collect::<Vec<f64>>();
+
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        let std =
+            (scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / scores.len() as f64).sqrt();
+
+        Ok(CvScores { scores, mean, std })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::InMemoryDataset, mlp::MLP, optim::Sgd};
+
+    /// 30 samples of class 0 followed by 10 of class 1, with the index as
+    /// the feature.
+    fn imbalanced() -> InMemoryDataset {
+        let xs = (0..40).map(|i| vec![i as f64]).collect();
+        let ys = (0..40).map(|i| vec![(i >= 30) as u8 as f64]).collect();
+        InMemoryDataset::new(xs, ys)
+    }
+
+    fn ones(subset: &Subset) -> usize {
+        subset.indices.iter().filter(|i| **i >= 30).count()
+    }
+
+    fn sorted(parts: &[&Subset]) -> Vec<usize> {
+        let mut indices = parts
+            .iter()
+            .flat_map(|part| part.indices.iter().copied())
+            .collect::<Vec<usize>>();
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn splits_are_disjoint_and_cover_the_dataset() {
+        let dataset = imbalanced();
+        for stratify in [false, true] {
+            let split = train_val_test_split(&dataset, 0.2, 0.3, stratify, 5);
+            assert_eq!(
+                sorted(&[&split.train, &split.val, &split.test]),
+                (0..40).collect::<Vec<usize>>()
+            );
+
+            let folds = k_fold(&dataset, 4, stratify, 5);
+            let vals = folds.iter().map(|(_, val)| val).collect::<Vec<&Subset>>();
+            assert_eq!(sorted(&vals), (0..40).collect::<Vec<usize>>());
+            for (train, val) in folds.iter() {
+                assert_eq!(sorted(&[train, val]), (0..40).collect::<Vec<usize>>());
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_splits_keep_the_class_ratio() {
+        let dataset = imbalanced();
+
+        let split = train_val_test_split(&dataset, 0.2, 0.3, true, 5);
+        assert_eq!((split.val.len(), ones(&split.val)), (8, 2));
+        assert_eq!((split.test.len(), ones(&split.test)), (12, 3));
+        assert_eq!((split.train.len(), ones(&split.train)), (20, 5));
+
+        for (_, val) in k_fold(&dataset, 5, true, 5) {
+            assert_eq!((val.len(), ones(&val)), (8, 2));
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_split() {
+        let dataset = imbalanced();
+        let (a, b) = (
+            train_val_test_split(&dataset, 0.2, 0.3, false, 5),
+            train_val_test_split(&dataset, 0.2, 0.3, false, 5),
+        );
+        assert_eq!(a.train.indices, b.train.indices);
+        assert_eq!(a.val.indices, b.val.indices);
+        assert_eq!(a.test.indices, b.test.indices);
+        let other = train_val_test_split(&dataset, 0.2, 0.3, false, 6);
+        assert_ne!(a.train.indices, other.train.indices);
+
+        let (a, b) = (k_fold(&dataset, 4, true, 5), k_fold(&dataset, 4, true, 5));
+        for ((_, a), (_, b)) in a.iter().zip(&b) {
+            assert_eq!(a.indices, b.indices);
+        }
+    }
+
+    #[test]
+    fn cross_validation_checks_k_first() {
+        let dataset = InMemoryDataset::new(vec![vec![0.0]; 3], vec![vec![1.0]; 3]);
+        let cv = CrossValidation::new(4, Loss::Mse, Metric::Mse).epochs(1);
+        let result = cv.run(&dataset, || MLP::new(1, vec![1]), || Sgd::new(0.1));
+        assert_eq!(
+            result.unwrap_err(),
+            CvError::TooFewSamples { k: 4, samples: 3 }
+        );
+
+        let cv = CrossValidation::new(3, Loss::Mse, Metric::Mse).epochs(1);
+        let scores = cv
+            .run(&dataset, || MLP::new(1, vec![1]), || Sgd::new(0.1))
+            .unwrap();
+        assert_eq!(scores.scores.len(), 3);
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::data::InMemoryDataset;
+
+/// A column picked by position or, when the file has names for its
+/// columns, by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Self {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Self {
+        Column::Name(name.to_string())
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Index(index) => write!(f, "#{}", index),
+            Column::Name(name) => write!(f, "{:?}", name),
+        }
+    }
+}
+
+/// What to do with a cell that is empty or holds one of the missing markers.
+///
+/// `Fill` and `Mean` only impute features: a made-up target would be a wrong
+/// label, e.g. the mean of two class indices, so a missing target is an
+/// error under them. `DropRow` drops rows missing either.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingPolicy {
+    Error,
+    DropRow,
+    Fill(f64),
+    /// Replaces the cell with the mean of the present values of its column.
+    Mean,
+}
+
+#[derive(Debug)]
+pub enum TabularError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    NotAnObject {
+        line: usize,
+    },
+    UnknownColumn(Column),
+    Parse {
+        line: usize,
+        column: String,
+        value: String,
+    },
+    Missing {
+        line: usize,
+        column: String,
+    },
+    EmptyColumn(String),
+}
+
+impl fmt::Display for TabularError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TabularError::Io(err) => write!(f, "io error: {}", err),
+            TabularError::Csv(err) => write!(f, "csv error: {}", err),
+            TabularError::Json { line, source } => {
+                write!(f, "line {}: invalid json: {}", line, source)
+            }
+            TabularError::NotAnObject { line } => {
+                write!(f, "line {}: expected a json object", line)
+            }
+            TabularError::UnknownColumn(column) => write!(f, "unknown column {}", column),
+            TabularError::Parse {
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "line {}: column {}: {:?} is not a number",
+                line, column, value
+            ),
+            TabularError::Missing { line, column } => {
+                write!(f, "line {}: column {}: missing value", line, column)
+            }
+            TabularError::EmptyColumn(column) => {
+                write!(f, "column {} has no values to take the mean of", column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TabularError {}
+
+impl From<io::Error> for TabularError {
+    fn from(err: io::Error) -> Self {
+        TabularError::Io(err)
+    }
+}
+
+impl From<csv::Error> for TabularError {
+    fn from(err: csv::Error) -> Self {
+        TabularError::Csv(err)
+    }
+}
+
+/// Reads CSV or JSON Lines files into an `InMemoryDataset`, taking the
+/// features from `features` (every other column when unset) and the target
+/// from `target`.
+///
+/// JSON Lines files hold one object per line. Their columns are named by the
+/// keys of the first object, in sorted order.
+#[derive(Debug, Clone)]
+pub struct TabularLoader {
+    pub target: Column,
+    pub features: Option<Vec<Column>>,
+    pub has_headers: bool,
+    pub delimiter: u8,
+    pub missing: MissingPolicy,
+    pub missing_markers: Vec<String>,
+}
+
+/// A row of selected cells, with the line it came from for error messages.
+type Row = (usize, Vec<Option<f64>>);
+
+impl TabularLoader {
+    pub fn new(target: impl Into<Column>) -> Self {
+        TabularLoader {
+            target: target.into(),
+            features: None,
+            has_headers: true,
+            delimiter: b',',
+            missing: MissingPolicy::Error,
+            missing_markers: vec!["".to_string(), "NA".to_string(), "NaN".to_string()],
+        }
+    }
+
+    pub fn features<C: Into<Column>>(mut self, features: Vec<C>) -> Self {
+        self.features = Some(features.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn missing(mut self, missing: MissingPolicy) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    pub fn load_csv(&self, path: impl AsRef<Path>) -> Result<InMemoryDataset, TabularError> {
+        self.read_csv(File::open(path)?)
+    }
+
+    pub fn load_jsonl(&self, path: impl AsRef<Path>) -> Result<InMemoryDataset, TabularError> {
+        self.read_jsonl(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_csv<R: Read>(&self, reader: R) -> Result<InMemoryDataset, TabularError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.has_headers)
+            .delimiter(self.delimiter)
+            .from_reader(reader);
+
+        let names = if self.has_headers {
+            Some(
+                reader
+                    .headers()?
+                    .iter()
+                    .map(|name| name.trim().to_string())
+                    .collect::<Vec<String>>(),
+            )
+        } else {
+            None
+        };
+
+        let mut records = reader.records().peekable();
+        let width = match (&names, records.peek()) {
+            (Some(names), _) => names.len(),
+            (None, Some(Ok(record))) => record.len(),
+            _ => 0,
+        };
+        let (columns, column_names) = self.resolve(names.as_deref(), width)?;
+
+        let mut rows = Vec::new();
+        for record in records {
+            let record = record?;
+            let line = record.position().map_or(0, |p| p.line() as usize);
+
+            let cells = columns
+                .iter()
+                .zip(&column_names)
+                .map(|(i, name)| {
+                    let cell = record.get(*i).unwrap_or("");
+                    self.parse_cell(cell, line, name)
+                })
+                .collect::<Result<Vec<Option<f64>>, TabularError>>()?;
+            rows.push((line, cells));
+        }
+
+        self.build(rows, &column_names)
+    }
+
+    pub fn read_jsonl<R: BufRead>(&self, reader: R) -> Result<InMemoryDataset, TabularError> {
+        let mut objects = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line_text = line?;
+            if line_text.trim().is_empty() {
+                continue;
+            }
+
+            let line = i + 1;
+            let value = serde_json::from_str::<serde_json::Value>(&line_text)
+                .map_err(|source| TabularError::Json { line, source })?;
+            match value {
+                serde_json::Value::Object(object) => objects.push((line, object)),
+                _ => return Err(TabularError::NotAnObject { line }),
+            }
+        }
+
+        let names = objects
+            .first()
+            .map(|(_, object)| object.keys().cloned().collect::<Vec<String>>())
+            .unwrap_or_default();
+        let (columns, column_names) = self.resolve(Some(&names), names.len())?;
+
+        let mut rows = Vec::new();
+        for (line, object) in objects {
+            let cells = columns
+                .iter()
+                .zip(&column_names)
+                .map(|(i, name)| match object.get(&names[*i]) {
+                    None | Some(serde_json::Value::Null) => self.parse_cell("", line, name),
+                    Some(serde_json::Value::Number(number)) => Ok(number.as_f64()),
+                    Some(serde_json::Value::Bool(b)) => Ok(Some(*b as u8 as f64)),
+                    Some(serde_json::Value::String(s)) => self.parse_cell(s, line, name),
+                    Some(other) => Err(TabularError::Parse {
+                        line,
+                        column: name.clone(),
+                        value: other.to_string(),
+                    }),
+                })
+                .collect::<Result<Vec<Option<f64>>, TabularError>>()?;
+            rows.push((line, cells));
+        }
+
+        self.build(rows, &column_names)
+    }
+
+    /// Positions of the feature columns followed by the target column, and
+    /// their display names.
+    fn resolve(
+        &self,
+        names: Option<&[String]>,
+        width: usize,
+    ) -> Result<(Vec<usize>, Vec<String>), TabularError> {
+        let position = |column: &Column| match (column, names) {
+            (Column::Index(i), _) if *i < width => Ok(*i),
+            (Column::Name(name), Some(names)) => names
+                .iter()
+                .position(|n| n == name)
+                .ok_or_else(|| TabularError::UnknownColumn(column.clone())),
+            _ => Err(TabularError::UnknownColumn(column.clone())),
+        };
+
+        let target = position(&self.target)?;
+        let mut columns = match &self.features {
+            Some(features) => features
+                .iter()
+                .map(position)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => (0..width).filter(|i| *i != target).collect(),
+        };
+        columns.push(target);
+
+        let column_names = columns
+            .iter()
+            .map(|i| match names {
+                Some(names) => names[*i].clone(),
+                None => format!("#{}", i),
+            })
+            .collect();
+
+        Ok((columns, column_names))
+    }
+
+    fn parse_cell(
+        &self,
+        cell: &str,
+        line: usize,
+        column: &str,
+    ) -> Result<Option<f64>, TabularError> {
+        let cell = cell.trim();
+        if self.missing_markers.iter().any(|marker| marker == cell) {
+            return Ok(None);
+        }
+
+        cell.parse::<f64>()
+            .map(Some)
+            .map_err(|_| TabularError::Parse {
+                line,
+                column: column.to_string(),
+                value: cell.to_string(),
+            })
+    }
+
+    /// Applies the missing value policy and splits the target off each row.
+    fn build(
+        &self,
+        rows: Vec<Row>,
+        column_names: &[String],
+    ) -> Result<InMemoryDataset, TabularError> {
+        // the target is the last column and is never imputed
+        let target = column_names.len() - 1;
+        let fill = match self.missing {
+            MissingPolicy::Mean => {
+                let mut fill = Vec::with_capacity(target);
+                for (c, name) in column_names[..target].iter().enumerate() {
+                    let present = rows
+                        .iter()
+                        .filter_map(|(_, cells)| cells[c])
+                        .collect::<Vec<f64>>();
+                    if present.is_empty() {
+                        return Err(TabularError::EmptyColumn(name.clone()));
+                    }
+                    fill.push(present.iter().sum::<f64>() / present.len() as f64);
+                }
+                Some(fill)
+            }
+            MissingPolicy::Fill(value) => Some(vec![value; target]),
+            MissingPolicy::Error | MissingPolicy::DropRow => None,
+        };
+
+        let mut dataset = InMemoryDataset::default();
+        'rows: for (line, cells) in rows {
+            let mut values = Vec::with_capacity(cells.len());
+            for (c, cell) in cells.into_iter().enumerate() {
+                match (cell, &fill, self.missing) {
+                    (Some(value), _, _) => values.push(value),
+                    (None, _, MissingPolicy::DropRow) => continue 'rows,
+                    (None, Some(fill), _) if c < target => values.push(fill[c]),
+                    (None, _, _) => {
+                        return Err(TabularError::Missing {
+                            line,
+                            column: column_names[c].clone(),
+                        })
+                    }
+                }
+            }
+
+            let target = values.pop().unwrap();
+            dataset.xs.push(values);
+            dataset.ys.push(vec![target]);
+        }
+
+        Ok(dataset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "a,b,label\n1,2,0\n3,4,1\n";
+
+    fn csv(loader: &TabularLoader, text: &str) -> Result<InMemoryDataset, TabularError> {
+        loader.read_csv(text.as_bytes())
+    }
+
+    fn jsonl(loader: &TabularLoader, text: &str) -> Result<InMemoryDataset, TabularError> {
+        loader.read_jsonl(text.as_bytes())
+    }
+
+    #[test]
+    fn csv_takes_every_other_column_as_features() {
+        let dataset = csv(&TabularLoader::new("label"), CSV).unwrap();
+        assert_eq!(dataset.xs, vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+        assert_eq!(dataset.ys, vec![vec![0.0], vec![1.0]]);
+
+        let path = std::env::temp_dir().join(format!("tabular-{}.csv", std::process::id()));
+        std::fs::write(&path, CSV).unwrap();
+        let loaded = TabularLoader::new("label").load_csv(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!((loaded.xs, loaded.ys), (dataset.xs, dataset.ys));
+    }
+
+    #[test]
+    fn columns_are_selected_by_name_or_index_in_order() {
+        let loader = TabularLoader::new(0).features(vec!["label", "b"]);
+        let dataset = csv(&loader, CSV).unwrap();
+        assert_eq!(dataset.xs, vec![vec![0.0, 2.0], vec![1.0, 4.0]]);
+        assert_eq!(dataset.ys, vec![vec![1.0], vec![3.0]]);
+
+        let loader = TabularLoader::new(2).features(vec![1]);
+        assert_eq!(csv(&loader, CSV).unwrap().xs, vec![vec![2.0], vec![4.0]]);
+    }
+
+    #[test]
+    fn headerless_files_are_selected_by_index() {
+        let text = "1;2;0\n3;4;1\n";
+        let loader = TabularLoader::new(2).has_headers(false).delimiter(b';');
+        let dataset = csv(&loader, text).unwrap();
+        assert_eq!(dataset.xs, vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+        assert_eq!(dataset.ys, vec![vec![0.0], vec![1.0]]);
+
+        let loader = TabularLoader::new("label")
+            .has_headers(false)
+            .delimiter(b';');
+        assert!(matches!(
+            csv(&loader, text),
+            Err(TabularError::UnknownColumn(Column::Name(name))) if name == "label"
+        ));
+    }
+
+    #[test]
+    fn bad_input_is_reported_with_its_line_and_column() {
+        let loader = TabularLoader::new("label");
+
+        let err = csv(&loader, "a,b,label\n1,2,0\n3,x,1\n").unwrap_err();
+        assert!(
+            matches!(&err, TabularError::Parse { line: 3, column, value }
+            if column == "b" && value == "x")
+        );
+        assert_eq!(err.to_string(), "line 3: column b: \"x\" is not a number");
+
+        let err = csv(&loader, "a,b,label\n1,NA,0\n").unwrap_err();
+        assert!(matches!(err, TabularError::Missing { line: 2, column } if column == "b"));
+
+        let loader = TabularLoader::new("label").features(vec![7]);
+        assert!(matches!(
+            csv(&loader, CSV),
+            Err(TabularError::UnknownColumn(Column::Index(7)))
+        ));
+    }
+
+    #[test]
+    fn missing_features_follow_the_policy() {
+        let text = "a,b,label\n1,,0\n3,4,1\nNA,8,1\n";
+
+        let dataset = csv(
+            &TabularLoader::new("label").missing(MissingPolicy::DropRow),
+            text,
+        );
+        assert_eq!(dataset.unwrap().xs, vec![vec![3.0, 4.0]]);
+
+        let loader = TabularLoader::new("label").missing(MissingPolicy::Fill(-1.0));
+        let dataset = csv(&loader, text).unwrap();
+        assert_eq!(
+            dataset.xs,
+            vec![vec![1.0, -1.0], vec![3.0, 4.0], vec![-1.0, 8.0]]
+        );
+
+        let dataset = csv(
+            &TabularLoader::new("label").missing(MissingPolicy::Mean),
+            text,
+        );
+        assert_eq!(
+            dataset.unwrap().xs,
+            vec![vec![1.0, 6.0], vec![3.0, 4.0], vec![2.0, 8.0]]
+        );
+    }
+
+    #[test]
+    fn missing_targets_are_never_imputed() {
+        let text = "a,label\n1,0\n2,\n3,1\n";
+
+        for policy in [MissingPolicy::Mean, MissingPolicy::Fill(0.5)] {
+            let err = csv(&TabularLoader::new("label").missing(policy), text).unwrap_err();
+            assert!(matches!(err, TabularError::Missing { line: 3, column } if column == "label"));
+        }
+
+        let loader = TabularLoader::new("label").missing(MissingPolicy::DropRow);
+        let dataset = csv(&loader, text).unwrap();
+        assert_eq!(dataset.xs, vec![vec![1.0], vec![3.0]]);
+        assert_eq!(dataset.ys, vec![vec![0.0], vec![1.0]]);
+    }
+
+    #[test]
+    fn jsonl_columns_are_the_sorted_keys_of_the_first_object() {
+        let text = "{\"y\": 1, \"b\": true, \"a\": \"2.5\"}\n\n{\"a\": 3, \"y\": 0, \"b\": null}\n";
+        let loader = TabularLoader::new("y").missing(MissingPolicy::Fill(0.0));
+        let dataset = jsonl(&loader, text).unwrap();
+        assert_eq!(dataset.xs, vec![vec![2.5, 1.0], vec![3.0, 0.0]]);
+        assert_eq!(dataset.ys, vec![vec![1.0], vec![0.0]]);
+
+        let loader = TabularLoader::new("y");
+        assert!(matches!(
+            jsonl(&loader, "{\"y\": 1}\n[1]\n"),
+            Err(TabularError::NotAnObject { line: 2 })
+        ));
+        assert!(matches!(
+            jsonl(&loader, "{\"y\": 1}\n{\"y\": \n"),
+            Err(TabularError::Json { line: 2, .. })
+        ));
+        assert!(matches!(
+            jsonl(&loader, "{\"y\": [1]}\n"),
+            Err(TabularError::Parse { line: 1, .. })
+        ));
+    }
+}
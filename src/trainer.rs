@@ -0,0 +1,665 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checkpoint::Checkpoint,
+    data::{to_values, DataLoader, Dataset, InMemoryDataset},
+    engine::*,
+    loss::Loss,
+    metrics::{Metric, Outputs},
+    module::Module,
+    optim::{Optimizer, OptimizerState},
+    random,
+    scheduler::LrScheduler,
+};
+
+/// Named values recorded for one epoch, e.g. `loss` and `val_loss`.
+pub type Logs = BTreeMap<String, f64>;
+
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    pub epochs: Vec<Logs>,
+}
+
+impl History {
+    /// The value of `key` for every epoch that recorded it.
+    pub fn get(&self, key: &str) -> Vec<f64> {
+        self.epochs
+            .iter()
+            .filter_map(|logs| logs.get(key).copied())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Hooks called by `Trainer::fit`. Returning `Control::Stop` from
+/// `on_epoch_end` ends training after the current epoch.
+pub trait Callback {
+    fn on_batch_end(&mut self, _batch: usize, _loss: f64) {}
+
+    fn on_epoch_end(&mut self, _epoch: usize, _logs: &Logs, _model: &dyn Module) -> Control {
+        Control::Continue
+    }
+}
+
+/// Whether a monitored value should go down (losses) or up (accuracy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Min,
+    Max,
+}
+
+impl Mode {
+    fn improved(&self, current: f64, best: Option<f64>, min_delta: f64) -> bool {
+        match (self, best) {
+            (_, None) => true,
+            (Mode::Min, Some(best)) => current < best - min_delta,
+            (Mode::Max, Some(best)) => current > best + min_delta,
+        }
+    }
+}
+
+/// Stops training once `monitor` hasn't improved for `patience` epochs.
+pub struct EarlyStopping {
+    pub monitor: String,
+    pub mode: Mode,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub stopped_epoch: Option<usize>,
+    best: Option<f64>,
+    wait: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: &str, mode: Mode, patience: usize) -> Self {
+        EarlyStopping {
+            monitor: monitor.to_string(),
+            mode,
+            patience,
+            min_delta: 0.0,
+            stopped_epoch: None,
+            best: None,
+            wait: 0,
+        }
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_epoch_end(&mut self, epoch: usize, logs: &Logs, _model: &dyn Module) -> Control {
+        let Some(&current) = logs.get(&self.monitor) else {
+            return Control::Continue;
+        };
+
+        if self.mode.improved(current, self.best, self.min_delta) {
+            self.best = Some(current);
+            self.wait = 0;
+        } else {
+            self.wait += 1;
+            if self.wait >= self.patience {
+                self.stopped_epoch = Some(epoch);
+                return Control::Stop;
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Keeps a copy of the parameters from the epoch where `monitor` was best,
+/// and writes it to `path` when one is set.
+pub struct BestCheckpoint {
+    pub monitor: String,
+    pub mode: Mode,
+    pub best: Option<f64>,
+    pub best_epoch: Option<usize>,
+    pub weights: Option<Vec<f64>>,
+    /// The model's `Module::buffers` from the same epoch.
+    pub buffers: Option<Vec<f64>>,
+    pub path: Option<PathBuf>,
+    /// The last error writing to `path`, since callbacks can't return one.
+    pub error: Option<io::Error>,
+}
+
+impl BestCheckpoint {
+    pub fn new(monitor: &str, mode: Mode) -> Self {
+        BestCheckpoint {
+            monitor: monitor.to_string(),
+            mode,
+            best: None,
+            best_epoch: None,
+            weights: None,
+            buffers: None,
+            path: None,
+            error: None,
+        }
+    }
+
+    /// Saves every new best to `path` as a `Checkpoint` of the epoch, the
+    /// parameters and the buffers. It holds no optimizer state, so it is for
+    /// restoring the model with `Checkpoint::load`, `restore_params` and
+    /// `restore_buffers`, not for resuming training.
+    pub fn save_to(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Writes the best weights and buffers back into `model`. Returns
+    /// `false` if no checkpoint has been taken yet.
+    pub fn restore(&self, model: &dyn Module) -> bool {
+        let Some(weights) = &self.weights else {
+            return false;
+        };
+
+        for (p, w) in model.parameters().iter().zip(weights) {
+            p.0.borrow_mut().data = *w;
+        }
+        if let Some(buffers) = &self.buffers {
+            model.load_buffers(buffers);
+        }
+        true
+    }
+}
+
+impl Callback for BestCheckpoint {
+    fn on_epoch_end(&mut self, epoch: usize, logs: &Logs, model: &dyn Module) -> Control {
+        if let Some(&current) = logs.get(&self.monitor) {
+            if self.mode.improved(current, self.best, 0.0) {
+                self.best = Some(current);
+                self.best_epoch = Some(epoch);
+                let weights = Checkpoint::params_of(&model.parameters());
+                let buffers = model.buffers();
+
+                if let Some(path) = &self.path {
+                    let checkpoint = Checkpoint {
+                        epoch: epoch as u64 + 1,
+                        step: 0,
+                        params: weights.clone(),
+                        buffers: buffers.clone(),
+                        optimizer: OptimizerState::Stateless,
+                        rng: random::state(),
+                        loader_rng: None,
+                        scheduler_epoch: None,
+                    };
+                    if let Err(e) = checkpoint.save(path) {
+                        self.error = Some(e);
+                    }
+                }
+                self.weights = Some(weights);
+                self.buffers = Some(buffers);
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Runs the forward / loss / backward / update loop over mini-batches.
+pub struct Trainer<'a, O: Optimizer> {
+    pub model: &'a dyn Module,
+    pub loss: Loss,
+    pub optimizer: O,
+    pub metrics: Vec<Metric>,
+    /// What the metrics take the model outputs to be.
+    pub outputs: Outputs,
+    pub epochs: usize,
+    /// Epochs completed so far. `fit` trains from here up to `epochs`.
+    pub epoch: usize,
+    /// Optimizer steps taken so far.
+    pub step: u64,
+    pub scheduler: Option<LrScheduler>,
+}
+
+impl<'a, O: Optimizer> Trainer<'a, O> {
+    pub fn new(model: &'a dyn Module, loss: Loss, optimizer: O) -> Self {
+        Trainer {
+            model,
+            loss,
+            optimizer,
+            metrics: Vec::new(),
+            outputs: Outputs::Logits,
+            epochs: 100,
+            epoch: 0,
+            step: 0,
+            scheduler: None,
+        }
+    }
+
+    /// Sets the learning rate of the optimizer from `scheduler` before every
+    /// epoch.
+    pub fn scheduler(mut self, scheduler: LrScheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Metrics to log next to the loss, under `Metric::name`.
+    pub fn metrics(mut self, metrics: Vec<Metric>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Set to `Outputs::Probabilities` when the model ends in a sigmoid or
+    /// softmax, so the metrics don't read its outputs as logits.
+    pub fn outputs(mut self, outputs: Outputs) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    /// Trains on the batches of `loader` from epoch `epoch` up to `epochs`
+    /// and returns the logs of every epoch trained. The model is in training
+    /// mode while it is updated and left in eval mode.
+    /// `loss` is the mean training loss of the epoch and the metrics are
+    /// computed on the outputs seen during it. The same keys prefixed with
+    /// `val_` are added when `validation` is given.
+    ///
+    /// A loader without batches leaves nothing to train on, so the model is
+    /// left untouched and the history is empty.
+    pub fn fit(
+        &mut self,
+        loader: &mut DataLoader,
+        validation: Option<&dyn Dataset>,
+        callbacks: &mut [&mut dyn Callback],
+    ) -> History {
+        let mut history = History::default();
+        if loader.is_empty() {
+            return history;
+        }
+        let params = self.model.parameters();
+
+        for epoch in self.epoch..self.epochs {
+            if let Some(scheduler) = &self.scheduler {
+                self.optimizer.set_lr(scheduler.lr());
+            }
+            self.model.train();
+            let mut total_loss = 0.0;
+            let mut outputs = Vec::new();
+            let mut targets = Vec::new();
+
+            for (i, batch) in loader.iter().enumerate() {
+                let ypred = self.model.forward_batch(&batch.inputs());
+
+                let loss = self.loss.call(&ypred, &batch.ys);
+
+                self.optimizer.zero_grad(&params);
+                loss.backward();
+                self.optimizer.step(&params);
+                self.step += 1;
+
+                let loss = loss.borrow().data;
+                total_loss += loss * batch.len() as f64;
+                outputs.extend(ypred.iter().map(|row| to_data(row)));
+                targets.extend(batch.ys);
+
+                for callback in callbacks.iter_mut() {
+                    callback.on_batch_end(i, loss);
+                }
+            }
+
+            let mut logs = self.compute_metrics(&outputs, &targets);
+            logs.insert("loss".to_string(), total_loss / outputs.len() as f64);
+
+            if let Some(validation) = validation {
+                for (key, value) in self.evaluate(validation) {
+                    logs.insert(format!("val_{}", key), value);
+                }
+            }
+
+            let mut control = Control::Continue;
+            for callback in callbacks.iter_mut() {
+                if callback.on_epoch_end(epoch, &logs, self.model) == Control::Stop {
+                    control = Control::Stop;
+                }
+            }
+
+            history.epochs.push(logs);
+            self.epoch = epoch + 1;
+            if let Some(scheduler) = &mut self.scheduler {
+                scheduler.step();
+            }
+
+            if control == Control::Stop {
+                break;
+            }
+        }
+
+        self.model.eval();
+        history
+    }
+
+    /// Snapshots the model, the optimizer, the scheduler, the counters and
+    /// the RNGs. Taken between calls to `fit`, resuming from it continues the
+    /// run exactly, except for callbacks, which keep their own state.
+    pub fn checkpoint(&self, loader: &DataLoader) -> Checkpoint {
+        Checkpoint {
+            epoch: self.epoch as u64,
+            step: self.step,
+            params: Checkpoint::params_of(&self.model.parameters()),
+            buffers: self.model.buffers(),
+            optimizer: self.optimizer.state(),
+            rng: random::state(),
+            loader_rng: loader.rng_state(),
+            scheduler_epoch: self.scheduler.as_ref().map(|s| s.epoch),
+        }
+    }
+
+    /// Restores a `checkpoint` into this trainer, its model and `loader`.
+    /// Fails if only one of the checkpoint and the trainer has a scheduler.
+    pub fn resume(&mut self, checkpoint: &Checkpoint, loader: &mut DataLoader) -> io::Result<()> {
+        match (&mut self.scheduler, checkpoint.scheduler_epoch) {
+            (Some(scheduler), Some(epoch)) => scheduler.epoch = epoch,
+            (None, None) => {}
+            (Some(_), None) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the checkpoint has no scheduler position",
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the checkpoint has a scheduler position but the trainer has no scheduler",
+                ))
+            }
+        }
+        checkpoint.restore_params(&self.model.parameters())?;
+        checkpoint.restore_buffers(self.model)?;
+        self.optimizer.load_state(checkpoint.optimizer.clone())?;
+        self.epoch = checkpoint.epoch as usize;
+        self.step = checkpoint.step;
+        random::set_state(&checkpoint.rng);
+        loader.set_rng_state(checkpoint.loader_rng.as_ref());
+        Ok(())
+    }
+
+    /// The loss and metrics of the model on `dataset`, without updating it.
+    /// Switches the model to eval mode. An empty dataset gives empty logs.
+    pub fn evaluate(&self, dataset: &dyn Dataset) -> Logs {
+        self.model.eval();
+        let InMemoryDataset { xs, ys } = InMemoryDataset::collect(dataset);
+        if xs.is_empty() {
+            return Logs::new();
+        }
+        let inputs = xs.iter().map(|x| to_values(x)).collect::<Vec<Vec<Value>>>();
+        let ypred = self.model.forward_batch(&inputs);
+
+        let outputs = ypred.iter().map(|row| to_data(row)).collect::<Vec<_>>();
+        let mut logs = self.compute_metrics(&outputs, &ys);
+        logs.insert(
+            "loss".to_string(),
+            self.loss.call(&ypred, &ys).borrow().data,
+        );
+        logs
+    }
+
+    pub fn predict(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        xs.iter().map(|x| self.model.predict(x)).collect()
+    }
+
+    fn compute_metrics(&self, outputs: &[Vec<f64>], ys: &[Vec<f64>]) -> Logs {
+        self.metrics
+            .iter()
+            .map(|metric| {
+                let value = metric.compute_with(self.outputs, outputs, ys);
+                (metric.name(), value)
+            })
+            .collect()
+    }
+}
+
+fn to_data(row: &[Value]) -> Vec<f64> {
+    row.iter().map(|v| v.borrow().data).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        datasets::make_moons,
+        mlp::{Layer, MLP},
+        module::Sequential,
+        norm::BatchNorm1d,
+        optim::Adam,
+        scheduler::{LrScheduler, Schedule},
+    };
+
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Logs every call it gets into `events`, which may be shared with other
+    /// recorders, and keeps the parameters seen at the end of every epoch.
+    struct Recorder {
+        name: &'static str,
+        events: Rc<RefCell<Vec<String>>>,
+        params: Vec<Vec<f64>>,
+    }
+
+    impl Recorder {
+        fn new(name: &'static str, events: &Rc<RefCell<Vec<String>>>) -> Self {
+            Recorder {
+                name,
+                events: events.clone(),
+                params: Vec::new(),
+            }
+        }
+    }
+
+    impl Callback for Recorder {
+        fn on_batch_end(&mut self, batch: usize, _loss: f64) {
+            self.events
+                .borrow_mut()
+                .push(format!("{} batch {}", self.name, batch));
+        }
+
+        fn on_epoch_end(&mut self, epoch: usize, _logs: &Logs, model: &dyn Module) -> Control {
+            self.events
+                .borrow_mut()
+                .push(format!("{} epoch {}", self.name, epoch));
+            self.params.push(Checkpoint::params_of(&model.parameters()));
+            Control::Continue
+        }
+    }
+
+    fn bits(model: &dyn Module) -> Vec<u64> {
+        model
+            .parameters()
+            .iter()
+            .map(|p| p.borrow().data.to_bits())
+            .collect()
+    }
+
+    #[test]
+    fn resuming_a_checkpoint_continues_the_run_exactly() {
+        let dataset = make_moons(24, 0.1, 3);
+        let schedule = || {
+            LrScheduler::new(
+                0.05,
+                Schedule::Step {
+                    step_size: 2,
+                    gamma: 0.5,
+                },
+            )
+        };
+
+        random::seed(11);
+        let straight = MLP::new(2, vec![4, 1]);
+        let mut loader = DataLoader::new(&dataset, 5).shuffle(4);
+        let mut trainer = Trainer::new(&straight, Loss::Mse, Adam::new(0.05))
+            .scheduler(schedule())
+            .epochs(6);
+        trainer.fit(&mut loader, None, &mut []);
+
+        random::seed(11);
+        let first = MLP::new(2, vec![4, 1]);
+        let mut loader = DataLoader::new(&dataset, 5).shuffle(4);
+        let mut trainer = Trainer::new(&first, Loss::Mse, Adam::new(0.05))
+            .scheduler(schedule())
+            .epochs(3);
+        trainer.fit(&mut loader, None, &mut []);
+        let bytes = trainer.checkpoint(&loader).to_bytes();
+
+        random::seed(99);
+        let resumed = MLP::new(2, vec![4, 1]);
+        let mut loader = DataLoader::new(&dataset, 5).shuffle(0);
+        let mut trainer = Trainer::new(&resumed, Loss::Mse, Adam::new(0.05))
+            .scheduler(schedule())
+            .epochs(6);
+        trainer
+            .resume(&Checkpoint::from_bytes(&bytes).unwrap(), &mut loader)
+            .unwrap();
+        assert_eq!(trainer.scheduler.as_ref().unwrap().epoch, 3);
+        trainer.fit(&mut loader, None, &mut []);
+
+        assert_eq!(bits(&resumed), bits(&straight));
+        assert_eq!(trainer.optimizer.lr, 0.05 * 0.25);
+    }
+
+    #[test]
+    fn resuming_restores_batch_norm_statistics() {
+        let dataset = make_moons(24, 0.1, 3);
+        let model = || {
+            Sequential::new()
+                .then(Layer::new(2, 4))
+                .then(BatchNorm1d::new(4))
+                .then(Layer::new(4, 1))
+        };
+
+        random::seed(5);
+        let trained = model();
+        let mut loader = DataLoader::new(&dataset, 6);
+        let mut trainer = Trainer::new(&trained, Loss::Mse, Adam::new(0.05)).epochs(2);
+        trainer.fit(&mut loader, None, &mut []);
+        let checkpoint = Checkpoint::from_bytes(&trainer.checkpoint(&loader).to_bytes()).unwrap();
+        assert_eq!(checkpoint.buffers.len(), 8);
+
+        let resumed = model();
+        let mut trainer = Trainer::new(&resumed, Loss::Mse, Adam::new(0.05));
+        trainer.resume(&checkpoint, &mut loader).unwrap();
+
+        assert_eq!(resumed.buffers(), trained.buffers());
+        assert_eq!(trainer.evaluate(&dataset), {
+            let trainer = Trainer::new(&trained, Loss::Mse, Adam::new(0.05));
+            trainer.evaluate(&dataset)
+        });
+    }
+
+    #[test]
+    fn resuming_without_the_checkpointed_scheduler_fails() {
+        let dataset = make_moons(8, 0.1, 3);
+        let model = MLP::new(2, vec![2, 1]);
+        let mut loader = DataLoader::new(&dataset, 4);
+        let trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05))
+            .scheduler(LrScheduler::new(0.05, Schedule::Exponential(0.9)));
+        let checkpoint = trainer.checkpoint(&loader);
+
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05));
+        assert!(trainer.resume(&checkpoint, &mut loader).is_err());
+    }
+
+    #[test]
+    fn fit_lowers_the_loss() {
+        let dataset = make_moons(32, 0.1, 3);
+        random::seed(2);
+        let model = MLP::new(2, vec![8, 1]);
+        let mut loader = DataLoader::new(&dataset, 8).shuffle(1);
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05)).epochs(30);
+
+        let losses = trainer.fit(&mut loader, None, &mut []).get("loss");
+        assert_eq!(losses.len(), 30);
+        assert!(losses[29] < 0.5 * losses[0]);
+    }
+
+    #[test]
+    fn early_stopping_waits_for_patience_epochs() {
+        let dataset = make_moons(8, 0.1, 3);
+        let model = MLP::new(2, vec![2, 1]);
+        let mut loader = DataLoader::new(&dataset, 4);
+        // a zero learning rate never improves on the first epoch
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.0)).epochs(10);
+        let mut stopping = EarlyStopping::new("loss", Mode::Min, 3);
+
+        let history = trainer.fit(&mut loader, None, &mut [&mut stopping]);
+        assert_eq!(history.epochs.len(), 4);
+        assert_eq!(stopping.stopped_epoch, Some(3));
+        assert_eq!(trainer.epoch, 4);
+    }
+
+    #[test]
+    fn best_checkpoint_writes_the_best_epoch_to_disk() {
+        let path = std::env::temp_dir().join(format!("best-{}.ckpt", std::process::id()));
+        let dataset = make_moons(16, 0.1, 3);
+        random::seed(4);
+        let model = MLP::new(2, vec![4, 1]);
+        let mut loader = DataLoader::new(&dataset, 4);
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05)).epochs(5);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut recorder = Recorder::new("recorder", &events);
+        // the loss falls every epoch, so its maximum is the first epoch
+        let mut best = BestCheckpoint::new("loss", Mode::Max).save_to(&path);
+
+        trainer.fit(&mut loader, None, &mut [&mut recorder, &mut best]);
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(best.error.is_none());
+        assert_eq!(best.best_epoch, Some(0));
+        assert_eq!(checkpoint.epoch, 1);
+        assert_eq!(checkpoint.params, recorder.params[0]);
+        assert_eq!(best.weights.as_ref(), Some(&recorder.params[0]));
+        assert_ne!(recorder.params[0], recorder.params[4]);
+
+        assert!(best.restore(&model));
+        assert_eq!(
+            Checkpoint::params_of(&model.parameters()),
+            recorder.params[0]
+        );
+    }
+
+    #[test]
+    fn callbacks_are_called_in_order() {
+        let dataset = make_moons(8, 0.1, 3);
+        let model = MLP::new(2, vec![2, 1]);
+        let mut loader = DataLoader::new(&dataset, 4);
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05)).epochs(2);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut a = Recorder::new("a", &events);
+        let mut b = Recorder::new("b", &events);
+
+        trainer.fit(&mut loader, None, &mut [&mut a, &mut b]);
+
+        let mut expected = Vec::new();
+        for epoch in 0..2 {
+            for batch in 0..2 {
+                expected.push(format!("a batch {}", batch));
+                expected.push(format!("b batch {}", batch));
+            }
+            expected.push(format!("a epoch {}", epoch));
+            expected.push(format!("b epoch {}", epoch));
+        }
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn empty_data_is_skipped() {
+        let empty = InMemoryDataset::new(Vec::new(), Vec::new());
+        let model = MLP::new(2, vec![2, 1]);
+        let before = bits(&model);
+        let mut loader = DataLoader::new(&empty, 4);
+        let mut trainer = Trainer::new(&model, Loss::Mse, Adam::new(0.05))
+            .metrics(vec![Metric::Accuracy])
+            .epochs(3);
+
+        assert!(trainer.fit(&mut loader, None, &mut []).epochs.is_empty());
+        assert_eq!(bits(&model), before);
+        assert_eq!(trainer.epoch, 0);
+        assert!(trainer.evaluate(&empty).is_empty());
+    }
+}
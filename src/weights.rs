@@ -0,0 +1,485 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+use safetensors::{tensor::Dtype, SafeTensors};
+
+use crate::{
+    mlp::{Layer, MLP},
+    onnx::Tensor,
+};
+
+/// Tensors by name, as read from a `.safetensors` or `.npz` file.
+pub type Tensors = BTreeMap<String, Tensor>;
+
+#[derive(Debug)]
+pub enum WeightsError {
+    Io(io::Error),
+    SafeTensors(safetensors::SafeTensorError),
+    Zip(zip::result::ZipError),
+    /// A `.npy` file that could not be parsed.
+    Npy(String),
+    UnsupportedDtype {
+        name: String,
+        dtype: String,
+    },
+    MissingTensor(String),
+    ShapeMismatch {
+        name: String,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeightsError::Io(err) => write!(f, "io error: {}", err),
+            WeightsError::SafeTensors(err) => write!(f, "safetensors error: {}", err),
+            WeightsError::Zip(err) => write!(f, "npz error: {}", err),
+            WeightsError::Npy(msg) => write!(f, "npy error: {}", msg),
+            WeightsError::UnsupportedDtype { name, dtype } => write!(
+                f,
+                "tensor {:?} has dtype {}, only 32 and 64-bit floats are supported",
+                name, dtype
+            ),
+            WeightsError::MissingTensor(name) => write!(f, "no tensor named {:?}", name),
+            WeightsError::ShapeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tensor {:?} has shape {:?}, the model expects {:?}",
+                name, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+impl From<io::Error> for WeightsError {
+    fn from(err: io::Error) -> Self {
+        WeightsError::Io(err)
+    }
+}
+
+impl From<safetensors::SafeTensorError> for WeightsError {
+    fn from(err: safetensors::SafeTensorError) -> Self {
+        WeightsError::SafeTensors(err)
+    }
+}
+
+impl From<zip::result::ZipError> for WeightsError {
+    fn from(err: zip::result::ZipError) -> Self {
+        WeightsError::Zip(err)
+    }
+}
+
+pub fn load_safetensors(path: impl AsRef<Path>) -> Result<Tensors, WeightsError> {
+    read_safetensors(&fs::read(path)?)
+}
+
+pub fn read_safetensors(bytes: &[u8]) -> Result<Tensors, WeightsError> {
+    let file = SafeTensors::deserialize(bytes)?;
+
+    let mut tensors = Tensors::new();
+    for (name, view) in file.tensors() {
+        let data = match view.dtype() {
+            Dtype::F64 => floats(view.data(), f64::from_le_bytes),
+            Dtype::F32 => floats(view.data(), |b| f32::from_le_bytes(b) as f64),
+            dtype => {
+                return Err(WeightsError::UnsupportedDtype {
+                    name,
+                    dtype: format!("{:?}", dtype),
+                })
+            }
+        };
+        let dims = view.shape().to_vec();
+        tensors.insert(name, Tensor { dims, data });
+    }
+    Ok(tensors)
+}
+
+pub fn load_npy(path: impl AsRef<Path>) -> Result<Tensor, WeightsError> {
+    read_npy(&fs::read(path)?)
+}
+
+/// Parses a `.npy` file of little-endian `f4` or `f8` values, in C or
+/// Fortran order.
+pub fn read_npy(bytes: &[u8]) -> Result<Tensor, WeightsError> {
+    let npy = |msg: &str| WeightsError::Npy(msg.to_string());
+
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(npy("missing the \\x93NUMPY magic string"));
+    }
+    let (header_len, start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+            12,
+        ),
+        version => return Err(WeightsError::Npy(format!("unknown version {}", version))),
+    };
+    let header = bytes
+        .get(start..start + header_len)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| npy("truncated or non-text header"))?;
+
+    let descr = header_value(header, "descr")
+        .map(|descr| descr.trim_matches(|c| c == '\'' || c == '"'))
+        .ok_or_else(|| npy("header has no descr"))?;
+    let fortran_order = header_value(header, "fortran_order") == Some("True");
+    let dims = header_value(header, "shape")
+        .ok_or_else(|| npy("header has no shape"))?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| npy("shape is not a tuple of integers"))?;
+
+    let body = &bytes[start + header_len..];
+    let mut data = match descr {
+        "<f8" => floats(body, f64::from_le_bytes),
+        "<f4" => floats(body, |b| f32::from_le_bytes(b) as f64),
+        dtype => {
+            return Err(WeightsError::Npy(format!(
+                "dtype {} is not supported, only <f4 and <f8 are",
+                dtype
+            )))
+        }
+    };
+
+    let len = dims.iter().product::<usize>();
+    if data.len() < len {
+        return Err(WeightsError::Npy(format!(
+            "expected {} values for shape {:?}, found {}",
+            len,
+            dims,
+            data.len()
+        )));
+    }
+    data.truncate(len);
+
+    if fortran_order && dims.len() > 1 {
+        data = to_c_order(&data, &dims);
+    }
+    Ok(Tensor { dims, data })
+}
+
+pub fn load_npz(path: impl AsRef<Path>) -> Result<Tensors, WeightsError> {
+    read_npz(File::open(path)?)
+}
+
+/// Reads every `.npy` member of a `.npz` archive, named without the
+/// extension as `np.savez` names them.
+pub fn read_npz<R: Read + Seek>(reader: R) -> Result<Tensors, WeightsError> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    let mut tensors = Tensors::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = match file.name().strip_suffix(".npy") {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let tensor = read_npy(&bytes).map_err(|err| match err {
+            WeightsError::Npy(msg) => WeightsError::Npy(format!("{}: {}", name, msg)),
+            err => err,
+        })?;
+        tensors.insert(name, tensor);
+    }
+    Ok(tensors)
+}
+
+impl Layer {
+    /// Overwrites the weights and biases with `weight` of shape `[nout, nin]`
+    /// (the layout of PyTorch's `nn.Linear`) and `bias` of shape `[nout]`.
+    /// `names` are used in errors.
+    pub fn load_tensors(
+        &self,
+        weight: &Tensor,
+        bias: &Tensor,
+        names: (&str, &str),
+    ) -> Result<(), WeightsError> {
+        check_shape(names.0, weight, &[self.nout(), self.nin()])?;
+        check_shape(names.1, bias, &[self.nout()])?;
+
+        let nin = self.nin();
+        for (i, neuron) in self.0.iter().enumerate() {
+            for (w, data) in neuron.0.iter().zip(&weight.data[i * nin..(i + 1) * nin]) {
+                w.0.borrow_mut().data = *data;
+            }
+            neuron.1 .0.borrow_mut().data = bias.data[i];
+        }
+        Ok(())
+    }
+}
+
+impl MLP {
+    /// Loads layer `i` from the tensors `layers.{i}.weight` and
+    /// `layers.{i}.bias`.
+    pub fn load_tensors(&self, tensors: &Tensors) -> Result<(), WeightsError> {
+        self.load_tensors_with(tensors, |i, param| format!("layers.{}.{}", i, param))
+    }
+
+    /// Loads layer `i` from the tensors named `name(i, "weight")` and
+    /// `name(i, "bias")`. For a PyTorch `nn.Sequential` alternating linear
+    /// layers and activations that is `|i, param| format!("{}.{}", 2 * i, param)`.
+    ///
+    /// Every shape is checked before anything is written, so on error the
+    /// model is left as it was.
+    pub fn load_tensors_with<F>(&self, tensors: &Tensors, name: F) -> Result<(), WeightsError>
+    where
+        F: Fn(usize, &str) -> String,
+    {
+        let get = |name: &str| {
+            tensors
+                .get(name)
+                .ok_or_else(|| WeightsError::MissingTensor(name.to_string()))
+        };
+
+        let mut layers = Vec::with_capacity(self.0.len());
+        for (i, layer) in self.0.iter().enumerate() {
+            let (weight_name, bias_name) = (name(i, "weight"), name(i, "bias"));
+            let (weight, bias) = (get(&weight_name)?, get(&bias_name)?);
+            check_shape(&weight_name, weight, &[layer.nout(), layer.nin()])?;
+            check_shape(&bias_name, bias, &[layer.nout()])?;
+            layers.push((layer, weight, bias, weight_name, bias_name));
+        }
+
+        for (layer, weight, bias, weight_name, bias_name) in layers {
+            layer.load_tensors(weight, bias, (&weight_name, &bias_name))?;
+        }
+        Ok(())
+    }
+}
+
+fn check_shape(name: &str, tensor: &Tensor, expected: &[usize]) -> Result<(), WeightsError> {
+    if tensor.dims != expected {
+        return Err(WeightsError::ShapeMismatch {
+            name: name.to_string(),
+            expected: expected.to_vec(),
+            found: tensor.dims.clone(),
+        });
+    }
+    Ok(())
+}
+
+fn floats<const N: usize>(bytes: &[u8], from_le_bytes: fn([u8; N]) -> f64) -> Vec<f64> {
+    bytes
+        .chunks_exact(N)
+        .map(|b| from_le_bytes(b.try_into().unwrap()))
+        .collect()
+}
+
+/// The text of `key` in a `.npy` header such as
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }`.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(rest[..end].trim())
+}
+
+/// Reorders column-major data into row-major.
+fn to_c_order(data: &[f64], dims: &[usize]) -> Vec<f64> {
+    let mut strides = Vec::with_capacity(dims.len());
+    let mut stride = 1;
+    for dim in dims {
+        strides.push(stride);
+        stride *= dim;
+    }
+
+    (0..data.len())
+        .map(|c_index| {
+            // the multi-index of `c_index` in C order, flattened in Fortran order
+            let (mut rest, mut f_index) = (c_index, 0);
+            for (dim, stride) in dims.iter().zip(&strides).rev() {
+                f_index += (rest % dim) * stride;
+                rest /= dim;
+            }
+            data[f_index]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    use safetensors::tensor::TensorView;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    /// The parameters `MLP::new(2, vec![3, 1])` gets from the fixtures below,
+    /// in `parameters()` order.
+    const LOADED: [f64; 13] = [
+        1.0, 2.0, 0.5, 3.0, 4.0, 0.25, 5.0, 6.0, -0.75, 7.0, 8.0, 9.0, -2.0,
+    ];
+
+    fn f8(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn f4(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    /// A version 1 `.npy` file, its header padded to 64 bytes as numpy does.
+    fn npy(descr: &str, fortran_order: bool, shape: &str, body: &[u8]) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+            descr,
+            if fortran_order { "True" } else { "False" },
+            shape
+        );
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    fn npz(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, bytes) in files {
+            zip.start_file(format!("{}.npy", name), SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// `LOADED` as an `.npz`, with the first weight in Fortran order and the
+    /// first bias as `f4`.
+    fn npz_fixture() -> Vec<u8> {
+        npz(&[
+            (
+                "layers.0.weight",
+                npy("<f8", true, "(3, 2)", &f8(&[1.0, 3.0, 5.0, 2.0, 4.0, 6.0])),
+            ),
+            (
+                "layers.0.bias",
+                npy("<f4", false, "(3,)", &f4(&[0.5, 0.25, -0.75])),
+            ),
+            (
+                "layers.1.weight",
+                npy("<f8", false, "(1, 3)", &f8(&[7.0, 8.0, 9.0])),
+            ),
+            ("layers.1.bias", npy("<f8", false, "(1,)", &f8(&[-2.0]))),
+        ])
+    }
+
+    /// `LOADED` as a `.safetensors` file, with the first bias as `F32`.
+    fn safetensors_fixture() -> Vec<u8> {
+        let (w0, b0) = (f8(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), f4(&[0.5, 0.25, -0.75]));
+        let (w1, b1) = (f8(&[7.0, 8.0, 9.0]), f8(&[-2.0]));
+        let views = [
+            (
+                "layers.0.weight",
+                TensorView::new(Dtype::F64, vec![3, 2], &w0),
+            ),
+            ("layers.0.bias", TensorView::new(Dtype::F32, vec![3], &b0)),
+            (
+                "layers.1.weight",
+                TensorView::new(Dtype::F64, vec![1, 3], &w1),
+            ),
+            ("layers.1.bias", TensorView::new(Dtype::F64, vec![1], &b1)),
+        ];
+        safetensors::serialize(views.map(|(name, view)| (name, view.unwrap())), &None).unwrap()
+    }
+
+    fn params(model: &MLP) -> Vec<f64> {
+        model.parameters().iter().map(|p| p.borrow().data).collect()
+    }
+
+    #[test]
+    fn npz_and_safetensors_fixtures_load_into_an_mlp() {
+        for tensors in [
+            read_npz(Cursor::new(npz_fixture())).unwrap(),
+            read_safetensors(&safetensors_fixture()).unwrap(),
+        ] {
+            assert_eq!(tensors["layers.0.weight"].dims, [3, 2]);
+            assert_eq!(
+                tensors["layers.0.weight"].data,
+                [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+            );
+
+            let model = MLP::new(2, vec![3, 1]);
+            model.load_tensors(&tensors).unwrap();
+            assert_eq!(params(&model), LOADED);
+        }
+    }
+
+    #[test]
+    fn fortran_order_npy_is_read_in_c_order() {
+        let bytes = npy("<f8", true, "(2, 3)", &f8(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]));
+        let tensor = read_npy(&bytes).unwrap();
+        assert_eq!(tensor.dims, [2, 3]);
+        assert_eq!(tensor.data, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        let bytes = npy("<f8", false, "(2, 3)", &f8(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(read_npy(&bytes).unwrap(), tensor);
+    }
+
+    #[test]
+    fn unsupported_dtypes_are_errors() {
+        let bytes = npy("<i8", false, "(2,)", &[0; 16]);
+        let err = read_npz(Cursor::new(npz(&[("counts", bytes)]))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "npy error: counts: dtype <i8 is not supported, only <f4 and <f8 are"
+        );
+
+        let data = [0u8; 16];
+        let view = TensorView::new(Dtype::I64, vec![2], &data).unwrap();
+        let bytes = safetensors::serialize([("counts", view)], &None).unwrap();
+        let err = read_safetensors(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tensor \"counts\" has dtype I64, only 32 and 64-bit floats are supported"
+        );
+    }
+
+    #[test]
+    fn missing_tensors_and_wrong_shapes_leave_the_model_as_it_was() {
+        let model = MLP::new(2, vec![3, 1]);
+        let before = params(&model);
+
+        let mut tensors = read_npz(Cursor::new(npz_fixture())).unwrap();
+        let bias = tensors.remove("layers.1.bias").unwrap();
+        let err = model.load_tensors(&tensors).unwrap_err();
+        assert_eq!(err.to_string(), "no tensor named \"layers.1.bias\"");
+        assert_eq!(params(&model), before);
+
+        tensors.insert("layers.1.bias".to_string(), bias);
+        tensors.get_mut("layers.1.weight").unwrap().dims = vec![3, 1];
+        let err = model.load_tensors(&tensors).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tensor \"layers.1.weight\" has shape [3, 1], the model expects [1, 3]"
+        );
+        assert_eq!(params(&model), before);
+    }
+}